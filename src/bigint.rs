@@ -0,0 +1,344 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+// Each limb holds 9 decimal digits, which keeps parsing and printing trivial.
+const BASE: u64 = 1_000_000_000;
+
+// Arbitrary precision integer backing Funk's BigInteger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+  pub negative: bool,
+  // Little endian, no trailing zero limbs. Zero is an empty vector and never negative.
+  pub limbs: Vec<u32>
+}
+
+impl BigInt {
+  pub fn from_i64(value: i64) -> BigInt {
+    let negative = value < 0;
+    let mut magnitude = value.unsigned_abs();
+    let mut limbs = vec!();
+    while magnitude > 0 {
+      limbs.push((magnitude % BASE) as u32);
+      magnitude /= BASE;
+    }
+    BigInt{ negative, limbs }
+  }
+
+  // Decimal digits with an optional leading `-`.
+  pub fn parse(raw: &str) -> Option<BigInt> {
+    let (negative, digits) = match raw.strip_prefix('-') {
+      Some(digits) => (true, digits),
+      None => (false, raw)
+    };
+    if digits.is_empty() || ! digits.chars().all(|c| c.is_ascii_digit()) {
+      return None;
+    }
+
+    let mut limbs = vec!();
+    let mut end = digits.len();
+    while end > 0 {
+      let start = end.saturating_sub(9);
+      limbs.push(digits[start..end].parse::<u32>().unwrap());
+      end = start;
+    }
+    Some(BigInt{ negative, limbs }.normalized())
+  }
+
+  pub fn to_i64(&self) -> Option<i64> {
+    let mut magnitude: i128 = 0;
+    for limb in self.limbs.iter().rev() {
+      magnitude = magnitude * BASE as i128 + *limb as i128;
+      if magnitude > i64::MAX as i128 + 1 {
+        return None;
+      }
+    }
+    let value = if self.negative { -magnitude } else { magnitude };
+    if value < i64::MIN as i128 || value > i64::MAX as i128 {
+      None
+    } else {
+      Some(value as i64)
+    }
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.limbs.is_empty()
+  }
+
+  fn normalized(mut self) -> BigInt {
+    while self.limbs.last() == Some(&0) {
+      self.limbs.pop();
+    }
+    if self.limbs.is_empty() {
+      self.negative = false;
+    }
+    self
+  }
+
+  fn compare_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    if a.len() != b.len() {
+      return a.len().cmp(&b.len());
+    }
+    for (x, y) in a.iter().rev().zip(b.iter().rev()) {
+      if x != y {
+        return x.cmp(y);
+      }
+    }
+    Ordering::Equal
+  }
+
+  fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+      let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+      result.push((sum % BASE) as u32);
+      carry = sum / BASE;
+    }
+    if carry > 0 {
+      result.push(carry as u32);
+    }
+    result
+  }
+
+  // a - b, where |a| >= |b|.
+  fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, limb) in a.iter().enumerate() {
+      let mut diff = *limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+      borrow = 0;
+      if diff < 0 {
+        diff += BASE as i64;
+        borrow = 1;
+      }
+      result.push(diff as u32);
+    }
+    result
+  }
+
+  fn mul_small(a: &[u32], factor: u64) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0;
+    for limb in a {
+      let product = *limb as u64 * factor + carry;
+      result.push((product % BASE) as u32);
+      carry = product / BASE;
+    }
+    while carry > 0 {
+      result.push((carry % BASE) as u32);
+      carry /= BASE;
+    }
+    BigInt{ negative: false, limbs: result }.normalized().limbs
+  }
+
+  // Truncating division, the remainder takes the sign of the dividend. None when dividing by zero.
+  pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+    if other.is_zero() {
+      return None;
+    }
+
+    let mut quotient = vec!(0; self.limbs.len());
+    let mut remainder: Vec<u32> = vec!();
+    for i in (0..self.limbs.len()).rev() {
+      remainder.insert(0, self.limbs[i]);
+      remainder = BigInt{ negative: false, limbs: remainder }.normalized().limbs;
+
+      // Largest digit q with other * q <= remainder.
+      let (mut low, mut high) = (0, BASE - 1);
+      while low < high {
+        let mid = (low + high).div_ceil(2);
+        if BigInt::compare_magnitude(&BigInt::mul_small(&other.limbs, mid), &remainder) == Ordering::Greater {
+          high = mid - 1;
+        } else {
+          low = mid;
+        }
+      }
+      quotient[i] = low as u32;
+      remainder = BigInt::sub_magnitude(&remainder, &BigInt::mul_small(&other.limbs, low));
+      remainder = BigInt{ negative: false, limbs: remainder }.normalized().limbs;
+    }
+
+    let quotient = BigInt{ negative: self.negative != other.negative, limbs: quotient }.normalized();
+    let remainder = BigInt{ negative: self.negative, limbs: remainder }.normalized();
+    Some((quotient, remainder))
+  }
+}
+
+impl Ord for BigInt {
+  fn cmp(&self, other: &BigInt) -> Ordering {
+    match (self.negative, other.negative) {
+      (false, true) => Ordering::Greater,
+      (true, false) => Ordering::Less,
+      (false, false) => BigInt::compare_magnitude(&self.limbs, &other.limbs),
+      (true, true) => BigInt::compare_magnitude(&other.limbs, &self.limbs)
+    }
+  }
+}
+
+impl PartialOrd for BigInt {
+  fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Neg for BigInt {
+  type Output = BigInt;
+
+  fn neg(self) -> BigInt {
+    BigInt{ negative: ! self.negative, limbs: self.limbs }.normalized()
+  }
+}
+
+impl Add for BigInt {
+  type Output = BigInt;
+
+  fn add(self, other: BigInt) -> BigInt {
+    if self.negative == other.negative {
+      return BigInt{ negative: self.negative, limbs: BigInt::add_magnitude(&self.limbs, &other.limbs) }.normalized();
+    }
+    match BigInt::compare_magnitude(&self.limbs, &other.limbs) {
+      Ordering::Less => BigInt{ negative: other.negative, limbs: BigInt::sub_magnitude(&other.limbs, &self.limbs) }.normalized(),
+      _ => BigInt{ negative: self.negative, limbs: BigInt::sub_magnitude(&self.limbs, &other.limbs) }.normalized()
+    }
+  }
+}
+
+impl Sub for BigInt {
+  type Output = BigInt;
+
+  fn sub(self, other: BigInt) -> BigInt {
+    self + (-other)
+  }
+}
+
+impl Mul for BigInt {
+  type Output = BigInt;
+
+  fn mul(self, other: BigInt) -> BigInt {
+    let mut result = vec!(0u64; self.limbs.len() + other.limbs.len() + 1);
+    for (i, a) in self.limbs.iter().enumerate() {
+      let mut carry = 0;
+      for (j, b) in other.limbs.iter().enumerate() {
+        let current = result[i + j] + *a as u64 * *b as u64 + carry;
+        result[i + j] = current % BASE;
+        carry = current / BASE;
+      }
+      let mut k = i + other.limbs.len();
+      while carry > 0 {
+        let current = result[k] + carry;
+        result[k] = current % BASE;
+        carry = current / BASE;
+        k += 1;
+      }
+    }
+    let limbs = result.into_iter().map(|limb| limb as u32).collect();
+    BigInt{ negative: self.negative != other.negative, limbs }.normalized()
+  }
+}
+
+impl Div for BigInt {
+  type Output = BigInt;
+
+  // Panics on zero, use `div_rem` to check first.
+  fn div(self, other: BigInt) -> BigInt {
+    self.div_rem(&other).expect("Division by zero").0
+  }
+}
+
+impl fmt::Display for BigInt {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.limbs.is_empty() {
+      return write!(f, "0");
+    }
+    if self.negative {
+      write!(f, "-")?;
+    }
+    write!(f, "{}", self.limbs[self.limbs.len() - 1])?;
+    for limb in self.limbs.iter().rev().skip(1) {
+      write!(f, "{:09}", limb)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn big(raw: &str) -> BigInt {
+    BigInt::parse(raw).unwrap()
+  }
+
+  #[test]
+  fn parses_and_prints_across_limbs() {
+    for raw in ["0", "7", "-7", "999999999", "1000000000", "-1000000000000000001", "123456789012345678901234567890"] {
+      assert_eq!(big(raw).to_string(), raw);
+    }
+    assert_eq!(big("000120").to_string(), "120");
+    assert_eq!(big("-000"), BigInt::from_i64(0));
+    assert!(! big("-0").negative);
+    assert_eq!(BigInt::parse(""), None);
+    assert_eq!(BigInt::parse("-"), None);
+    assert_eq!(BigInt::parse("12a"), None);
+    assert_eq!(BigInt::from_i64(i64::MIN).to_string(), "-9223372036854775808");
+  }
+
+  #[test]
+  fn converts_back_to_i64_only_in_range() {
+    assert_eq!(big("9223372036854775807").to_i64(), Some(i64::MAX));
+    assert_eq!(big("-9223372036854775808").to_i64(), Some(i64::MIN));
+    assert_eq!(big("9223372036854775808").to_i64(), None);
+    assert_eq!(big("-9223372036854775809").to_i64(), None);
+    assert_eq!(big("100000000000000000000000000000").to_i64(), None);
+  }
+
+  #[test]
+  fn adds_and_subtracts_with_carries_and_signs() {
+    assert_eq!((big("999999999999999999") + big("1")).to_string(), "1000000000000000000");
+    assert_eq!((big("1000000000000000000") - big("1")).to_string(), "999999999999999999");
+    assert_eq!((big("5") - big("12")).to_string(), "-7");
+    assert_eq!((big("-5") + big("12")).to_string(), "7");
+    assert_eq!((big("-5") - big("-5")).to_string(), "0");
+    assert!(! (big("-5") + big("5")).negative);
+  }
+
+  #[test]
+  fn multiplies() {
+    assert_eq!((big("123456789012345678901234567890") * big("987654321")).to_string(), "121932631124828532112482853211126352690");
+    assert_eq!((big("-99999999999") * big("99999999999")).to_string(), "-9999999999800000000001");
+    assert_eq!((big("-3") * big("0")).to_string(), "0");
+  }
+
+  #[test]
+  fn divides_truncating_towards_zero() {
+    let cases = [("7", "2", "3", "1"), ("-7", "2", "-3", "-1"), ("7", "-2", "-3", "1"), ("-7", "-2", "3", "-1"), ("1", "5", "0", "1")];
+    for (a, b, quotient, remainder) in cases {
+      let (q, r) = big(a).div_rem(&big(b)).unwrap();
+      assert_eq!((q.to_string(), r.to_string()), (quotient.to_string(), remainder.to_string()), "{} / {}", a, b);
+    }
+    assert_eq!(big("5").div_rem(&big("0")), None);
+  }
+
+  #[test]
+  fn long_division_finds_every_digit() {
+    // Divisors of several limbs make the binary search for each quotient digit work hardest.
+    let dividends = ["123456789012345678901234567890123456789", "1000000000000000000000000000", "999999999999999999999999999"];
+    let divisors = ["1", "999999999", "1000000000", "123456789123", "-98765432109876543210"];
+    for a in dividends {
+      for b in divisors {
+        let (q, r) = big(a).div_rem(&big(b)).unwrap();
+        assert_eq!(q.clone() * big(b) + r.clone(), big(a), "{} / {}", a, b);
+        assert_eq!(BigInt::compare_magnitude(&r.limbs, &big(b).limbs), Ordering::Less, "{} % {}", a, b);
+      }
+    }
+    assert_eq!((big("121932631124828532112482853211126352690") / big("987654321")).to_string(), "123456789012345678901234567890");
+  }
+
+  #[test]
+  fn orders_by_sign_then_magnitude() {
+    let mut values = [big("10"), big("-1000000000000"), big("0"), big("-2"), big("1000000000000")];
+    values.sort();
+    assert_eq!(values.iter().map(|v| v.to_string()).collect::<Vec<String>>(), vec!("-1000000000000", "-2", "0", "10", "1000000000000"));
+  }
+}
@@ -0,0 +1,1163 @@
+use std::collections::HashMap;
+use bigint::BigInt;
+use diagnostic::Diagnostic;
+use source_map::Span;
+use structs::structs::*;
+
+// Static checks over the whole program, run before anything is interpreted.
+pub struct Checker {
+  pub interfaces: HashMap<String, InterfaceDecl>,
+  pub klasses: HashMap<String, KlassDecl>,
+  pub enums: HashMap<String, EnumDecl>,
+  pub funcs: HashMap<String, FunctionDecl>,
+  // Innermost scope last. Functions start over with only their parameters, like the interpreter.
+  pub scopes: Vec<HashMap<String, Local>>,
+  // How many scopes there were outside each lambda being checked, innermost last. A lambda holds a copy
+  // of those, so it cannot assign to them.
+  pub lambdas: Vec<usize>,
+  // Return type of the function being checked, None at the top level.
+  pub return_type: Option<Type>,
+  // Name of the function being checked.
+  pub function: String,
+  // Types of the `return`s seen while inferring a lambda's return type.
+  pub returns: Vec<Type>,
+  // Every variable and parameter with the type it was given or inferred, in source order.
+  pub declared: Vec<Declared>,
+  // Functions implemented in Rust, the same ones the interpreter gets.
+  pub natives: Env,
+  // The node being checked, errors point here.
+  pub span: Span
+}
+
+// A variable in scope. Only those declared with `mut` can be assigned to, parameters and bindings never.
+pub struct Local {
+  pub ty: Type,
+  pub mutable: bool
+}
+
+// Parameters and `when` or `catch` bindings.
+fn fixed(bindings: HashMap<String, Type>) -> HashMap<String, Local> {
+  bindings.into_iter().map(|(name, ty)| (name, Local{ ty, mutable: false })).collect()
+}
+
+// The variable a place starts from, `p` for `p.items[0]`.
+fn root(target: &AST) -> Option<&str> {
+  match target {
+    AST::Expression(Expr::Identifier(name), _) => Some(name),
+    AST::Expression(Expr::Field{ object, .. } | Expr::Index{ object, .. }, _) => root(object),
+    _ => None
+  }
+}
+
+pub struct Declared {
+  pub scope: String,
+  pub name: String,
+  pub ty: Type,
+  pub inferred: bool
+}
+
+impl Checker {
+  pub fn new(natives: Env) -> Checker {
+    Checker{ interfaces: HashMap::new(), klasses: HashMap::new(), enums: HashMap::new(), funcs: HashMap::new(),
+      scopes: vec!(HashMap::new()), lambdas: vec!(), return_type: None, function: "<top>".to_string(), returns: vec!(), declared: vec!(), natives,
+      span: Span::default() }
+  }
+
+  pub fn check(&mut self, program: &mut [AST]) -> Result<(), Diagnostic> {
+    for decl in EnumDecl::builtins() {
+      self.enums.insert(decl.name.clone(), decl);
+    }
+    for decl in KlassDecl::builtins() {
+      self.klasses.insert(decl.name.clone(), decl);
+    }
+
+    // Declarations are visible to the whole program, wherever they are written. A name is declared once,
+    // as the interpreter would refuse the second one anyway.
+    for ast in program.iter() {
+      self.span = ast.span().clone();
+      match ast {
+        AST::Statement(Statement::Interface(decl), _) => {
+          self.expect_new_type(&decl.name)?;
+          self.interfaces.insert(decl.name.clone(), decl.clone());
+        },
+        AST::Statement(Statement::Klass(decl), _) => {
+          self.expect_new_type(&decl.name)?;
+          self.klasses.insert(decl.name.clone(), decl.clone());
+        },
+        AST::Statement(Statement::Enum(decl), _) => {
+          self.expect_new_type(&decl.name)?;
+          self.enums.insert(decl.name.clone(), decl.clone());
+        },
+        AST::Statement(Statement::Function(decl), _) if self.funcs.contains_key(&decl.name) => {
+          return Err(self.error(format!("Function [{}] is already defined", decl.name)))
+        },
+        AST::Statement(Statement::Function(decl), _) => { self.funcs.insert(decl.name.clone(), decl.clone()); },
+        _ => {}
+      }
+    }
+
+    // The builtin klasses implement nothing, only those in the program need a look.
+    for ast in program.iter() {
+      if let AST::Statement(Statement::Klass(klass), span) = ast {
+        self.span = span.clone();
+        self.check_klass(klass)?;
+      }
+    }
+
+    for ast in program.iter_mut() {
+      self.check_ast(ast)?;
+    }
+
+    // The interpreter calls `main` after the top level and exits with what it returns.
+    for ast in program.iter() {
+      if let AST::Statement(Statement::Function(main), span) = ast {
+        let params: Vec<Type> = main.params.iter().map(|p| p.argument().1).collect();
+        if main.name == "main" && (params != vec!(Type::Array(Box::new(Type::String))) || main.return_type != Type::Integer) {
+          self.span = span.clone();
+          return Err(self.error(format!("[main] must be declared as [funk main(Array<String> args) Integer], found [{}]", main.signature())))
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn error(&self, message: String) -> Diagnostic {
+    Diagnostic::error(message, self.span.clone())
+  }
+
+  // Interfaces, klasses and enums share one namespace, the builtin ones included.
+  fn expect_new_type(&self, name: &str) -> Result<(), Diagnostic> {
+    if self.interfaces.contains_key(name) || self.klasses.contains_key(name) || self.enums.contains_key(name) {
+      return Err(self.error(format!("Type [{}] is already defined", name)))
+    }
+    Ok(())
+  }
+
+  fn check_klass(&self, klass: &KlassDecl) -> Result<(), Diagnostic> {
+    for (i, method) in klass.methods.iter().enumerate() {
+      if klass.methods[..i].iter().any(|m| m.name == method.name) {
+        return Err(self.error(format!("Method [{}.{}] is defined twice", klass.name, method.name)))
+      }
+    }
+
+    for name in &klass.interfaces {
+      let interface = match self.interfaces.get(name) {
+        Some(interface) => interface,
+        None => return Err(self.error(format!("Klass [{}] implements unknown interface [{}]", klass.name, name)))
+      };
+
+      for expected in &interface.methods {
+        let found = match klass.methods.iter().find(|m| m.name == expected.name) {
+          Some(method) => method.signature(),
+          None => return Err(self.error(format!("Klass [{}] is missing [{}] from interface [{}]", klass.name, expected, name)))
+        };
+        if &found != expected {
+          return Err(self.error(format!("Klass [{}] has [{}] but interface [{}] requires [{}]", klass.name, found, name, expected)))
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn check_type_exists(&self, ty: &Type) -> Result<(), Diagnostic> {
+    match ty {
+      Type::Nullable(inner) => self.check_type_exists(inner),
+      Type::Function(params, ret) => {
+        for param in params {
+          self.check_type_exists(param)?;
+        }
+        self.check_type_exists(ret)
+      },
+      Type::Array(item) => self.check_type_exists(item),
+      Type::Hash(key, value) => {
+        self.check_type_exists(key)?;
+        self.check_type_exists(value)
+      },
+      Type::Named(name, args) => {
+        let expected = match self.enums.get(name) {
+          Some(decl) => decl.params.len(),
+          None if self.klasses.contains_key(name) || self.interfaces.contains_key(name) => 0,
+          None => return Err(self.error(format!("Unknown type [{}]", name)))
+        };
+        if args.len() != expected {
+          return Err(self.error(format!("[{}] takes {} type argument{}, found {}", name, expected, if expected == 1 { "" } else { "s" }, args.len())))
+        }
+        for arg in args {
+          self.check_type_exists(arg)?;
+        }
+        Ok(())
+      },
+      _ => Ok(())
+    }
+  }
+
+  fn declare(&mut self, name: String, ty: Type, mutable: bool) {
+    self.scopes.last_mut().unwrap().insert(name, Local{ ty, mutable });
+  }
+
+  fn is_variable(&self, name: &str) -> bool {
+    self.scopes.iter().any(|scope| scope.contains_key(name))
+  }
+
+  fn is_mutable(&self, name: &str) -> bool {
+    self.scopes.iter().rev().find_map(|scope| scope.get(name)).is_some_and(|local| local.mutable)
+  }
+
+  // Whether `name` comes from outside the innermost lambda being checked.
+  fn is_captured(&self, name: &str) -> bool {
+    let depth = self.scopes.iter().rposition(|scope| scope.contains_key(name));
+    matches!((depth, self.lambdas.last()), (Some(depth), Some(outside)) if depth < *outside)
+  }
+
+  fn lookup(&self, name: &str) -> Result<Type, Diagnostic> {
+    match self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+      Some(local) => Ok(local.ty.clone()),
+      None => Err(self.error(format!("Undefined variable [{}]", name)))
+    }
+  }
+
+  // Anything goes into Any, getting a value back out of it takes an `as` cast, which is checked at runtime.
+  // Infer is a type nothing has pinned down yet, like that of `null`, and fits anywhere.
+  fn assignable(&self, value: &Type, target: &Type) -> bool {
+    match (value, target) {
+      (Type::Infer, _) | (_, Type::Infer) | (_, Type::Any) => true,
+      (Type::Any, _) => false,
+      (Type::Nullable(value), Type::Nullable(target)) => self.assignable(value, target),
+      (Type::Nullable(_), _) => false,
+      (value, Type::Nullable(target)) => self.assignable(value, target),
+      // Arrays and Hashes are copied on assignment, so an Array<Integer> can stand in for an Array<Any>.
+      (Type::Array(value), Type::Array(target)) => self.assignable(value, target),
+      (Type::Hash(key, value), Type::Hash(target_key, target_value)) => {
+        self.assignable(key, target_key) && self.assignable(value, target_value)
+      },
+      // Enum values cannot change either, an Option<Integer> is also an Option<Integer?>.
+      (Type::Named(value, value_args), Type::Named(target, target_args)) if value == target => {
+        value_args.len() == target_args.len() && value_args.iter().zip(target_args).all(|(value, target)| self.assignable(value, target))
+      },
+      (Type::Named(value, _), Type::Named(target, target_args)) => {
+        target_args.is_empty() && self.klasses.get(value).is_some_and(|klass| klass.interfaces.contains(target))
+      },
+      (value, target) => value == target
+    }
+  }
+
+  // Numbers convert between each other, other casts go up or down from a klass to its interfaces.
+  // A nullable value can be cast to its non null type, which fails at runtime on null.
+  fn castable(&self, from: &Type, to: &Type) -> bool {
+    let numbers = [Type::Integer, Type::Decimal, Type::BigInteger];
+    match (from.non_null(), to.non_null()) {
+      (Type::Any, _) | (_, Type::Any) => true,
+      (from, to) if numbers.contains(&from) && numbers.contains(&to) => true,
+      (from, to) => self.assignable(&from, &to) || self.assignable(&to, &from)
+    }
+  }
+
+  fn expect_assignable(&self, value: &Type, target: &Type, what: String) -> Result<(), Diagnostic> {
+    if self.assignable(value, target) {
+      return Ok(());
+    }
+    if value.is_nullable() && ! target.is_nullable() {
+      return Err(self.error(format!("{} is [{}] and cannot hold null", what, target)))
+    }
+    Err(self.error(format!("{} expected [{}], found [{}]", what, target, value)))
+  }
+
+  // Type of an AST whose result is used, which rules out Void.
+  fn check_value(&mut self, ast: &mut AST) -> Result<Type, Diagnostic> {
+    let ty = self.check_ast(ast)?;
+    if ty == Type::Void {
+      match ast {
+        AST::Expression(Expr::Call{ name, .. }, _) => return Err(self.error(format!("[{}] returns Void, its result cannot be used", name))),
+        AST::Expression(Expr::MethodCall{ method, .. }, _) => return Err(self.error(format!("[{}] returns Void, its result cannot be used", method))),
+        _ => return Err(self.error(format!("A Void value cannot be used: [{:?}]", ast)))
+      }
+    }
+    Ok(ty)
+  }
+
+  // Like `check_value`, but a lambda can take the types it leaves out from `expected`, and so can the type
+  // arguments of an enum variant, `Option::None` in an `Option<Integer>`. An Integer literal becomes a
+  // BigInteger one where a BigInteger is expected.
+  fn check_value_as(&mut self, ast: &mut AST, expected: &Type) -> Result<Type, Diagnostic> {
+    match ast {
+      AST::Expression(expr @ Expr::Integer(_), _) if expected.non_null() == Type::BigInteger => {
+        if let Expr::Integer(value) = expr {
+          *expr = Expr::BigInteger(BigInt::from_i64(*value));
+        }
+        Ok(Type::BigInteger)
+      },
+      AST::Expression(Expr::Lambda(decl), _) => self.check_lambda(decl, Some(expected)),
+      AST::Expression(Expr::Variant{ enum_name, variant, args }, _) if self.enums.contains_key(enum_name) => {
+        self.check_variant(enum_name, variant, args, Some(expected))
+      },
+      ast => self.check_value(ast)
+    }
+  }
+
+  fn check_args(&mut self, name: &str, params: &[Type], args: &mut [AST]) -> Result<(), Diagnostic> {
+    if params.len() != args.len() {
+      return Err(self.error(format!("[{}] takes {} arguments but {} were given", name, params.len(), args.len())))
+    }
+    for (i, (param, arg)) in params.iter().zip(args).enumerate() {
+      let ty = self.check_value_as(arg, param)?;
+      self.expect_assignable(&ty, param, format!("Argument {} of [{}]", i + 1, name))?;
+    }
+    Ok(())
+  }
+
+  // An overloaded native takes the first of its signatures that the argument types fit.
+  fn check_native(&mut self, native: &Native, args: &mut [AST]) -> Result<Type, Diagnostic> {
+    if native.overloads.is_empty() {
+      return self.check_signature(native, args);
+    }
+    let types = args.iter_mut().map(|arg| self.check_value(arg)).collect::<Result<Vec<Type>, Diagnostic>>()?;
+    for candidate in native.candidates() {
+      let fits = if candidate.variadic { types.len() >= candidate.params.len() } else { types.len() == candidate.params.len() };
+      let mut bound = HashMap::new();
+      for (param, ty) in candidate.params.iter().zip(&types) {
+        param.bind(ty, &mut bound);
+      }
+      if fits && candidate.params.iter().zip(&types).all(|(param, ty)| self.assignable(ty, &param.substitute(&bound))) {
+        return Ok(candidate.return_type.substitute(&bound));
+      }
+    }
+    let found: Vec<String> = types.iter().map(|ty| ty.to_string()).collect();
+    let expected: Vec<String> = native.candidates().iter().map(|candidate| format!("[{}]", candidate.signature())).collect();
+    Err(self.error(format!("No [{}] takes ({}), there are {}", native.name, found.join(", "), expected.join(", "))))
+  }
+
+  fn check_signature(&mut self, native: &Native, args: &mut [AST]) -> Result<Type, Diagnostic> {
+    let fixed = native.params.len();
+    if native.variadic && args.len() < fixed {
+      return Err(self.error(format!("[{}] takes at least {} arguments but {} were given", native.name, fixed, args.len())))
+    }
+    if ! native.variadic && args.len() != fixed {
+      return Err(self.error(format!("[{}] takes {} arguments but {} were given", native.name, fixed, args.len())))
+    }
+    let (leading, rest) = args.split_at_mut(fixed);
+    let mut bound = HashMap::new();
+    self.check_generic_args(&native.name, &native.params, leading, &mut bound)?;
+    for arg in rest {
+      self.check_value(arg)?;
+    }
+    Ok(native.return_type.substitute(&bound))
+  }
+
+  // Type parameters in `params` take the types of the arguments standing in for them, left to right,
+  // unless `bound` already has them.
+  fn check_generic_args(&mut self, name: &str, params: &[Type], args: &mut [AST], bound: &mut HashMap<String, Type>) -> Result<(), Diagnostic> {
+    for (i, (param, arg)) in params.iter().zip(args).enumerate() {
+      let ty = self.check_value_as(arg, &param.substitute(bound))?;
+      param.bind(&ty, bound);
+      self.expect_assignable(&ty, &param.substitute(bound), format!("Argument {} of [{}]", i + 1, name))?;
+    }
+    Ok(())
+  }
+
+  // The type arguments of a generic enum come from `expected` first, then from the variant's values.
+  // Those neither gives are left to be inferred, `Option::None` on its own is an `Option<_>`.
+  fn check_variant(&mut self, enum_name: &str, variant: &str, args: &mut [AST], expected: Option<&Type>) -> Result<Type, Diagnostic> {
+    let decl = match self.enums.get(enum_name) {
+      Some(decl) => decl.clone(),
+      None => return Err(self.error(format!("Undefined enum [{}]", enum_name)))
+    };
+    let fields = match decl.variant(variant) {
+      Some(v) => v.fields.clone(),
+      None => return Err(self.error(format!("Enum [{}] has no variant [{}]", enum_name, variant)))
+    };
+    let name = format!("{}::{}", enum_name, variant);
+    if fields.len() != args.len() {
+      return Err(self.error(format!("[{}] takes {} arguments but {} were given", name, fields.len(), args.len())))
+    }
+
+    let mut bound = HashMap::new();
+    if let Some(Type::Named(expected, expected_args)) = expected.map(|ty| ty.non_null()) {
+      if expected == decl.name {
+        for (param, arg) in decl.params.iter().zip(expected_args) {
+          if arg != Type::Infer {
+            bound.insert(param.clone(), arg);
+          }
+        }
+      }
+    }
+    self.check_generic_args(&name, &fields, args, &mut bound)?;
+    Ok(Type::Named(decl.name.clone(), decl.params.iter().map(|param| bound.get(param).cloned().unwrap_or(Type::Infer)).collect()))
+  }
+
+  fn check_block(&mut self, body: &mut [AST], bindings: HashMap<String, Type>) -> Result<Type, Diagnostic> {
+    self.scopes.push(fixed(bindings));
+    let mut ty = Type::Void;
+    for ast in body.iter_mut() {
+      ty = self.check_ast(ast)?;
+    }
+    self.scopes.pop();
+    Ok(ty)
+  }
+
+  fn check_function(&mut self, decl: &mut FunctionDecl, this: Option<Type>) -> Result<(), Diagnostic> {
+    let mut params = HashMap::new();
+    for param in &decl.params {
+      let (name, ty) = param.argument();
+      if ty == Type::Infer {
+        return Err(Diagnostic::error(format!("Parameter [{}] of [{}] needs a type", name, decl.name), param.span().clone()))
+      }
+      self.check_type_exists(&ty)?;
+      self.record(&decl.name, &name, &ty, false);
+      params.insert(name, ty);
+    }
+    if let Some(this) = this {
+      params.insert("self".to_string(), this);
+    }
+    self.check_type_exists(&decl.return_type)?;
+
+    let scopes = std::mem::replace(&mut self.scopes, vec!(fixed(params)));
+    let lambdas = std::mem::take(&mut self.lambdas);
+    let return_type = self.return_type.replace(decl.return_type.clone());
+    let function = std::mem::replace(&mut self.function, decl.name.clone());
+    for ast in decl.body.iter_mut() {
+      self.check_ast(ast)?;
+    }
+    if decl.return_type != Type::Void && ! decl.body.iter().any(AST::always_leaves) {
+      return Err(self.error(format!("[{}] returns [{}] but can reach its end without a `return`", decl.name, decl.return_type)))
+    }
+    self.scopes = scopes;
+    self.lambdas = lambdas;
+    self.return_type = return_type;
+    self.function = function;
+    Ok(())
+  }
+
+  // Lambdas see the scope they are written in. Parameter and return types they leave out come from
+  // `expected`, and failing that the return type is whatever the body ends with.
+  fn check_lambda(&mut self, decl: &mut FunctionDecl, expected: Option<&Type>) -> Result<Type, Diagnostic> {
+    let (expected_params, expected_return) = match expected.map(|ty| ty.non_null()) {
+      Some(Type::Function(params, ret)) => {
+        if params.len() != decl.params.len() {
+          return Err(self.error(format!("Lambda takes {} parameters but [{}] is expected", decl.params.len(), expected.unwrap())))
+        }
+        (Some(params), Some(*ret))
+      },
+      _ => (None, None)
+    };
+
+    let mut params = HashMap::new();
+    for (i, param) in decl.params.iter_mut().enumerate() {
+      let (name, mut ty) = param.argument();
+      let inferred = ty == Type::Infer;
+      if inferred {
+        ty = match &expected_params {
+          Some(types) => types[i].clone(),
+          None => return Err(Diagnostic::error(format!("Cannot infer the type of lambda parameter [{}], give it a type", name), param.span().clone()))
+        };
+        // Keeps the span, errors about the parameter still point at what was written.
+        *param = AST::Expression(Expr::Argument{ name: name.clone(), ty: ty.clone() }, param.span().clone());
+      }
+      self.check_type_exists(&ty)?;
+      self.record(&decl.name, &name, &ty, inferred);
+      params.insert(name, ty);
+    }
+    if decl.return_type == Type::Infer {
+      if let Some(ret) = expected_return {
+        decl.return_type = ret;
+      }
+    }
+
+    self.lambdas.push(self.scopes.len());
+    self.scopes.push(fixed(params));
+    let return_type = self.return_type.replace(decl.return_type.clone());
+    let function = std::mem::replace(&mut self.function, decl.name.clone());
+    let returns = std::mem::take(&mut self.returns);
+    let mut last = Type::Void;
+    for ast in decl.body.iter_mut() {
+      last = self.check_ast(ast)?;
+    }
+    let body_returns = std::mem::replace(&mut self.returns, returns);
+    self.return_type = return_type;
+    self.function = function;
+    self.scopes.pop();
+    self.lambdas.pop();
+
+    if decl.return_type == Type::Infer {
+      // A body that ends by returning gives back what it returns, not the Void of the `return` itself.
+      if let (Some(first), true) = (body_returns.first(), decl.body.last().is_some_and(AST::always_leaves)) {
+        last = first.clone();
+      }
+      for ty in &body_returns {
+        self.expect_assignable(ty, &last, "Lambda return value".to_string())?;
+      }
+      decl.return_type = match last {
+        Type::Any => return Err(self.error("Cannot infer the return type of a lambda, give it one".to_string())),
+        ty => ty
+      };
+    }
+    Ok(decl.ty())
+  }
+
+  fn record(&mut self, scope: &str, name: &str, ty: &Type, inferred: bool) {
+    self.declared.push(Declared{ scope: scope.to_string(), name: name.to_string(), ty: ty.clone(), inferred });
+  }
+
+  fn check_ast(&mut self, ast: &mut AST) -> Result<Type, Diagnostic> {
+    let outer = std::mem::replace(&mut self.span, ast.span().clone());
+    let ty = match ast {
+      AST::Statement(statement, _) => self.check_statement(statement),
+      AST::Expression(expr, _) => self.check_expr(expr)
+    };
+    // Errors keep the span they were made with.
+    self.span = outer;
+    ty
+  }
+
+  fn check_statement(&mut self, statement: &mut Statement) -> Result<Type, Diagnostic> {
+    let ty = match statement {
+      Statement::Assignment(assign) if assign.ty == Type::Infer => {
+        // `let`: the initializer fixes the type for good.
+        let ty = self.check_value(&mut assign.value)?;
+        match &ty {
+          Type::Nullable(inner) if **inner == Type::Infer => return Err(self.error(format!("Cannot infer the type of [{}] from null, declare it", assign.name))),
+          Type::Any => return Err(self.error(format!("Cannot infer the type of [{}] from an untyped value, cast it with `as`", assign.name))),
+          ty if ! ty.is_known() => return Err(self.error(format!("Cannot infer the whole type of [{}] from [{}], declare it", assign.name, ty))),
+          _ => {}
+        }
+        assign.ty = ty.clone();
+        let scope = self.function.clone();
+        self.record(&scope, &assign.name, &ty, true);
+        self.declare(assign.name.clone(), ty.clone(), assign.mutable);
+        ty
+      },
+      Statement::Assignment(assign) => {
+        self.check_type_exists(&assign.ty)?;
+        let ty = self.check_value_as(&mut assign.value, &assign.ty)?;
+        self.expect_assignable(&ty, &assign.ty, format!("[{}]", assign.name))?;
+        let scope = self.function.clone();
+        self.record(&scope, &assign.name, &assign.ty, false);
+        self.declare(assign.name.clone(), assign.ty.clone(), assign.mutable);
+        assign.ty.clone()
+      },
+      Statement::Enum(decl) => {
+        for variant in &decl.variants {
+          for field in &variant.fields {
+            self.check_type_exists(field)?;
+          }
+        }
+        Type::Void
+      },
+      Statement::Interface(_) => Type::Void,
+      Statement::Function(decl) => {
+        self.check_function(decl, None)?;
+        Type::Void
+      },
+      Statement::Klass(klass) => {
+        for field in &klass.fields {
+          self.check_type_exists(&field.argument().1)?;
+        }
+        let this = Type::Named(klass.name.clone(), vec!());
+        for method in klass.methods.iter_mut() {
+          self.check_function(method, Some(this.clone()))?;
+        }
+        Type::Void
+      },
+      Statement::Return(value) => {
+        let expected = match &self.return_type {
+          Some(ty) => ty.clone(),
+          None => return Err(self.error("`return` outside of a function".to_string()))
+        };
+        match value {
+          None if expected == Type::Infer => self.returns.push(Type::Void),
+          None if expected != Type::Void => return Err(self.error(format!("`return` without a value in a function returning [{}]", expected))),
+          Some(_) if expected == Type::Void => return Err(self.error("Void function cannot return a value".to_string())),
+          Some(value) if expected == Type::Infer => {
+            let ty = self.check_value(value)?;
+            self.returns.push(ty);
+          },
+          Some(value) => {
+            let ty = self.check_value_as(value, &expected)?;
+            self.expect_assignable(&ty, &expected, "Return value".to_string())?;
+          },
+          None => {}
+        }
+        Type::Void
+      },
+      Statement::Reassign{ target, op, value } => {
+        let ty = self.check_target(target)?;
+        let value_ty = match op {
+          Some(op) => {
+            let right = self.check_value(value)?;
+            self.arithmetic(op, ty.clone(), right)?
+          },
+          None => self.check_value_as(value, &ty)?
+        };
+        self.expect_assignable(&value_ty, &ty, format!("[{}]", target.place().unwrap()))?;
+        Type::Void
+      },
+      Statement::Throw(value) => {
+        self.check_value(value)?;
+        Type::Void
+      },
+      Statement::Try(try_) => {
+        self.check_block(&mut try_.body, HashMap::new())?;
+        for catch in try_.catches.iter_mut() {
+          self.check_type_exists(&catch.ty)?;
+          let scope = self.function.clone();
+          self.record(&scope, &catch.name, &catch.ty, false);
+          self.check_block(&mut catch.body, HashMap::from([(catch.name.clone(), catch.ty.clone())]))?;
+        }
+        if let Some(finally) = &mut try_.finally {
+          self.check_block(finally, HashMap::new())?;
+        }
+        Type::Void
+      }
+    };
+    Ok(ty)
+  }
+
+  // Type of the variable, field or Array element being assigned to. Variables and fields have to be declared
+  // with `mut`, an element goes with the Array holding it. `self` and functions never change, and neither
+  // does anything a lambda captured.
+  fn check_target(&mut self, target: &mut AST) -> Result<Type, Diagnostic> {
+    if let Some(root) = root(target).filter(|root| self.is_captured(root)) {
+      return Err(self.error(format!("Cannot assign to [{}] in a lambda, [{}] is captured by value", target.place().unwrap(), root)))
+    }
+    let ty = match target {
+      AST::Expression(Expr::Identifier(name), _) if name == "self" => return Err(self.error("Cannot assign to [self], only to its fields".to_string())),
+      AST::Expression(Expr::Identifier(name), _) if ! self.is_variable(name) && self.funcs.contains_key(name) => {
+        return Err(self.error(format!("Cannot assign to function [{}]", name)))
+      },
+      AST::Expression(Expr::Identifier(name), _) if self.is_variable(name) && ! self.is_mutable(name) => {
+        return Err(self.error(format!("Cannot assign to [{}], declare it with `mut` to change it", name)))
+      },
+      AST::Expression(Expr::Field{ object, field, safe }, _) => {
+        let (klass, ty) = self.check_field(object, field, *safe)?;
+        if ! self.klasses[&klass].mutable.contains(field) {
+          return Err(self.error(format!("Cannot assign to field [{}.{}], declare it with `mut` to change it", klass, field)))
+        }
+        ty
+      },
+      AST::Expression(Expr::Index{ object, index }, _) => {
+        let array = self.check_target(object)?;
+        self.check_index(&array, index)?
+      },
+      target => self.check_value(target)?
+    };
+    Ok(ty)
+  }
+
+  // Klass the field belongs to and its type, nullable when reached through `?.`.
+  fn check_field(&mut self, object: &mut AST, field: &str, safe: bool) -> Result<(String, Type), Diagnostic> {
+    let object = self.check_value(object)?;
+    let name = self.receiver(&object, safe, field)?;
+    let ty = match self.klasses.get(&name) {
+      Some(klass) => match klass.fields.iter().map(|f| f.argument()).find(|f| f.0 == field) {
+        Some((_, ty)) => ty,
+        None => return Err(self.error(format!("Klass [{}] has no field [{}]", name, field)))
+      },
+      None => return Err(self.error(format!("[{}] has no field [{}]", name, field)))
+    };
+    Ok((name, if object.is_nullable() && ! ty.is_nullable() { Type::Nullable(Box::new(ty)) } else { ty }))
+  }
+
+  // Element type of `array`, which has to be an Array, at an Integer index.
+  fn check_index(&mut self, array: &Type, index: &mut AST) -> Result<Type, Diagnostic> {
+    let item = match array {
+      Type::Array(item) => *item.clone(),
+      Type::Nullable(_) => return Err(self.error(format!("[{}] may be null, unwrap it with ?: before indexing", array))),
+      Type::Any => return Err(self.error("Cannot index [Any], cast the value with `as` first".to_string())),
+      ty => return Err(self.error(format!("Cannot index [{}], only an Array", ty)))
+    };
+    let ty = self.check_value_as(index, &Type::Integer)?;
+    self.expect_assignable(&ty, &Type::Integer, "Index".to_string())?;
+    Ok(item)
+  }
+
+  fn check_expr(&mut self, expr: &mut Expr) -> Result<Type, Diagnostic> {
+    let ty = match expr {
+      Expr::Integer(_) => Type::Integer,
+      Expr::Decimal(_) => Type::Decimal,
+      Expr::BigInteger(_) => Type::BigInteger,
+      Expr::String(_) => Type::String,
+      Expr::Boolean(_) => Type::Boolean,
+      Expr::Argument{ .. } => Type::Any,
+      Expr::Null => Type::Nullable(Box::new(Type::Infer)),
+      Expr::Identifier(name) => match self.funcs.get(name) {
+        Some(decl) if ! self.is_variable(name) => decl.ty(),
+        _ => self.lookup(name)?
+      },
+      Expr::Lambda(decl) => self.check_lambda(decl, None)?,
+
+      Expr::Binary(binary) => {
+        let left = self.check_value(&mut binary.left)?;
+        let right = self.check_value(&mut binary.right)?;
+        self.arithmetic(&binary.op, left, right)?
+      },
+
+      Expr::Call{ name, args } => {
+        if let Some(decl) = self.funcs.get(name).cloned() {
+          let params: Vec<Type> = decl.params.iter().map(|p| p.argument().1).collect();
+          self.check_args(name, &params, args)?;
+          decl.return_type
+        } else if self.is_variable(name) {
+          match self.lookup(name)? {
+            Type::Function(params, ret) => {
+              self.check_args(name, &params, args)?;
+              *ret
+            },
+            ty => return Err(self.error(format!("[{}] is [{}], not a function", name, ty)))
+          }
+        } else if let Some(native) = self.natives.get_native(None, name).cloned() {
+          self.check_native(&native, args)?
+        } else if let Some(klass) = self.klasses.get(name).cloned() {
+          let fields: Vec<Type> = klass.fields.iter().map(|f| f.argument().1).collect();
+          self.check_args(name, &fields, args)?;
+          Type::Named(name.clone(), vec!())
+        } else {
+          return Err(self.error(format!("Undefined function [{}]", name)))
+        }
+      },
+
+      Expr::Field{ object, field, safe } => self.check_field(object, field, *safe)?.1,
+
+      Expr::Index{ object, index } => {
+        let array = self.check_value(object)?;
+        self.check_index(&array, index)?
+      },
+
+      Expr::MethodCall{ object, method, args, safe } => {
+        let object = self.check_value(object)?;
+        let name = self.receiver(&object, *safe, method)?;
+        let found = match self.klasses.get(&name) {
+          Some(klass) => klass.methods.iter().find(|m| &m.name == method).map(|m| m.signature()),
+          None => self.interfaces.get(&name).and_then(|i| i.methods.iter().find(|m| &m.name == method).cloned())
+        };
+        // A native method takes the value it is called on first.
+        let found = found.or_else(|| self.natives.get_native(Some(&name), method).map(|native| {
+          MethodSignature{ name: method.clone(), params: native.params[1..].to_vec(), return_type: native.return_type.clone() }
+        }));
+        let signature = match found {
+          Some(signature) => signature,
+          None => return Err(self.error(format!("[{}] has no method [{}]", name, method)))
+        };
+        self.check_args(method, &signature.params, args)?;
+
+        let ty = signature.return_type;
+        if object.is_nullable() && ty != Type::Void && ! ty.is_nullable() { Type::Nullable(Box::new(ty)) } else { ty }
+      },
+
+      Expr::Elvis{ left, right } => {
+        let left = self.check_value(left)?;
+        let right = self.check_value(right)?;
+        let inner = left.non_null();
+        self.expect_assignable(&right.non_null(), &inner, "Right side of ?:".to_string())?;
+        if right.is_nullable() { Type::Nullable(Box::new(inner)) } else { inner }
+      },
+
+      Expr::Variant{ enum_name, variant, args } if ! self.enums.contains_key(enum_name) && self.natives.mods.contains_key(enum_name) => {
+        match self.natives.get_native(Some(enum_name), variant).cloned() {
+          Some(native) => self.check_native(&native, args)?,
+          None => return Err(self.error(format!("Module [{}] has no [{}]", enum_name, variant)))
+        }
+      },
+
+      Expr::Variant{ enum_name, variant, args } => self.check_variant(enum_name, variant, args, None)?,
+
+      Expr::When(when) => {
+        let subject = self.check_value(&mut when.subject)?;
+        let (decl, type_args) = match &subject {
+          Type::Named(name, args) if self.enums.contains_key(name) => (self.enums[name].clone(), args.clone()),
+          Type::Any => return Err(self.error("Can only use `when` on enum values, cast the [Any] value with `as` first".to_string())),
+          ty => return Err(self.error(format!("Can only use `when` on enum values, found [{}]", ty)))
+        };
+
+        let mut result: Option<Type> = None;
+        for arm in when.arms.iter_mut() {
+          let mut bindings = HashMap::new();
+          if let Pattern::Variant{ enum_name, variant, bindings: names } = &arm.pattern {
+            if enum_name != &decl.name {
+              return Err(self.error(format!("Cannot match [{}::{}] against a value of enum [{}]", enum_name, variant, decl.name)))
+            }
+            let fields = match decl.variant(variant) {
+              Some(v) => v.fields.clone(),
+              None => return Err(self.error(format!("Enum [{}] has no variant [{}]", enum_name, variant)))
+            };
+            if fields.len() != names.len() {
+              return Err(self.error(format!("[{}::{}] holds {} values but the pattern binds {}", enum_name, variant, fields.len(), names.len())))
+            }
+            let bound: HashMap<String, Type> = decl.params.iter().cloned().zip(type_args.iter().cloned()).collect();
+            bindings.extend(names.iter().cloned().zip(fields.iter().map(|field| field.substitute(&bound))));
+          }
+
+          let ty = self.check_block(&mut arm.body, bindings)?;
+          result = match result {
+            None => Some(ty),
+            Some(prev) if prev == ty => Some(prev),
+            Some(_) => Some(Type::Any)
+          };
+        }
+
+        // Enums are closed, so a `when` without `_` must name every variant.
+        if ! when.arms.iter().any(|arm| arm.pattern == Pattern::Wildcard) {
+          let missing: Vec<String> = decl.variants.iter()
+            .filter(|v| ! when.arms.iter().any(|arm| matches!(&arm.pattern, Pattern::Variant{ variant, .. } if variant == &v.name)))
+            .map(|v| format!("{}::{}", decl.name, v.name))
+            .collect();
+          if ! missing.is_empty() {
+            return Err(self.error(format!("`when` on [{}] does not handle [{}]", subject, missing.join(", "))))
+          }
+        }
+        result.unwrap_or(Type::Void)
+      },
+
+      Expr::Cast{ value, ty } => {
+        self.check_type_exists(ty)?;
+        let from = self.check_value(value)?;
+        if ! self.castable(&from, ty) {
+          return Err(self.error(format!("Cannot cast [{}] to [{}]", from, ty)))
+        }
+        ty.clone()
+      },
+
+      Expr::Increment{ target, op, .. } => {
+        let ty = self.check_target(target)?;
+        let symbol = if *op == BinOp::Add { "++" } else { "--" };
+        match ty {
+          Type::Integer | Type::Decimal | Type::BigInteger => ty,
+          ty => return Err(self.error(format!("Cannot apply [{}] to [{}] of type [{}]", symbol, target.place().unwrap(), ty)))
+        }
+      }
+    };
+    Ok(ty)
+  }
+
+  // Type of `left op right`, only numbers take part and an Integer with a BigInteger makes a BigInteger.
+  fn arithmetic(&self, op: &BinOp, left: Type, right: Type) -> Result<Type, Diagnostic> {
+    if left.is_nullable() || right.is_nullable() {
+      return Err(self.error(format!("Cannot apply [{:?}] to [{}] and [{}], unwrap nullable values with ?: first", op, left, right)))
+    }
+    match (left, right) {
+      (Type::Integer, Type::Integer) => Ok(Type::Integer),
+      (Type::Decimal, Type::Decimal) => Ok(Type::Decimal),
+      (Type::BigInteger, Type::BigInteger) | (Type::BigInteger, Type::Integer) | (Type::Integer, Type::BigInteger) => Ok(Type::BigInteger),
+      (left, right) => Err(self.error(format!("Cannot apply [{:?}] to [{}] and [{}]", op, left, right)))
+    }
+  }
+
+  // Klass or interface name that a member is looked up on.
+  fn receiver(&self, object: &Type, safe: bool, member: &str) -> Result<String, Diagnostic> {
+    if object.is_nullable() && ! safe {
+      return Err(self.error(format!("[{}] may be null, use ?.{} instead", object, member)))
+    }
+    match object.non_null() {
+      Type::Named(name, _) => Ok(name),
+      Type::Any => Err(self.error(format!("[Any] has no member [{}], cast the value with `as` first", member))),
+      // Values whose methods are natives, `String` for "abc".len().
+      ty if self.natives.mods.contains_key(&ty.to_string()) => Ok(ty.to_string()),
+      ty => Err(self.error(format!("[{}] has no member [{}]", ty, member)))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cst::SourceFile;
+  use natives::{self, Options};
+  use source_map::{FileId, Files};
+  use super::*;
+
+  // Declared types of the program's variables, after checking it.
+  fn check(code: &str) -> Vec<String> {
+    let mut program = SourceFile::parse(code, FileId(0)).unwrap().to_ast(FileId(0)).unwrap();
+    let mut checker = Checker::new(natives::registry(&Options{ io: true, args: vec!(), seed: Some(0) }));
+    checker.check(&mut program).unwrap_or_else(|error| panic!("{}", error.message));
+    checker.declared.iter().map(|declared| format!("{}: {}", declared.name, declared.ty)).collect()
+  }
+
+  // The first error in `code`, as main.fk would report it.
+  fn error(code: &str) -> String {
+    let mut files = Files::new();
+    let file = files.add("main.fk", code);
+    let mut program = SourceFile::parse(code, file).unwrap().to_ast(file).unwrap();
+    let mut checker = Checker::new(natives::registry(&Options{ io: true, args: vec!(), seed: Some(0) }));
+    checker.check(&mut program).unwrap_err().render(&files)
+  }
+
+  #[test]
+  fn errors_point_at_the_node_that_failed() {
+    assert_eq!(error("let f = funk() Integer {\n  return \"x\"\n}"),
+      "error: Return value expected [Integer], found [String]\n --> main.fk:2:3\n  |\n2 |   return \"x\"\n  |   ^^^^^^^^^^");
+    assert_eq!(error("funk f(Integer a, b) {}"),
+      "error: Parameter [b] of [f] needs a type\n --> main.fk:1:19\n  |\n1 | funk f(Integer a, b) {}\n  |                   ^");
+    assert_eq!(error("funk main() Integer {\n  return 0\n}"),
+      "error: [main] must be declared as [funk main(Array<String> args) Integer], found [funk main() Integer]\n --> main.fk:1:1\n  |\n1 | funk main() Integer {\n  | ^^^^^^^^^^^^^^^^^^^^^");
+  }
+
+  #[test]
+  fn generic_natives_take_their_types_from_the_arguments() {
+    assert_eq!(check("Array<String> cards = \"a,b\".split(\",\")\nlet card = random::choice(cards)\nlet deck = random::shuffle(cards)"),
+      vec!("cards: Array<String>", "card: String", "deck: Array<String>"));
+  }
+
+  #[test]
+  fn main_takes_the_arguments_and_gives_the_exit_code() {
+    check("funk main(Array<String> args) Integer {\n  return args[0].len()\n}");
+  }
+
+  #[test]
+  #[should_panic(expected = "[main] must be declared as [funk main(Array<String> args) Integer], found [funk main(Array<String>) Void]")]
+  fn main_must_give_an_exit_code() {
+    check("funk main(Array<String> args) Void {\n}");
+  }
+
+  #[test]
+  #[should_panic(expected = "Argument 1 of [random::choice] expected [Array<T>], found [String]")]
+  fn generic_natives_still_check_the_shape() {
+    check("random::choice(\"abc\")");
+  }
+
+  #[test]
+  fn overloaded_natives_pick_the_signature_that_fits() {
+    assert_eq!(check("let a = math::abs(0 - 3)\nlet b = math::max(1.5, 0.5)\nInteger c = math::min(a, 2)"),
+      vec!("a: Integer", "b: Decimal", "c: Integer"));
+  }
+
+  #[test]
+  #[should_panic(expected = "No [math::max] takes (Integer, Decimal), there are [funk math::max(Integer, Integer) Integer], [funk math::max(Decimal, Decimal) Decimal]")]
+  fn overloaded_natives_reject_mixed_arguments() {
+    check("math::max(1, 0.5)");
+  }
+
+  #[test]
+  fn any_only_gives_values_back_through_a_cast() {
+    assert_eq!(check("Hash<String, Any> h = json::parse(\"{}\") as Hash<String, Any>\nInteger n = json::parse(\"1\") as Integer\nAny a = n"),
+      vec!("h: Hash<String, Any>", "n: Integer", "a: Any"));
+  }
+
+  #[test]
+  #[should_panic(expected = "[x] expected [Integer], found [Any]")]
+  fn any_is_not_assignable_to_a_concrete_type() {
+    check("Integer x = json::parse(\"\\\"s\\\"\")");
+  }
+
+  #[test]
+  #[should_panic(expected = "[Any] has no member [len], cast the value with `as` first")]
+  fn any_has_no_members() {
+    check("json::parse(\"1\").len()");
+  }
+
+  #[test]
+  fn option_and_result_are_generic() {
+    assert_eq!(check("Option<Integer> none = Option::None\nlet some = Option::Some(1)\nResult<Decimal, String> r = Result::Err(\"no\")\nlet n = when (some) {\n  Option::Some(v) => v + 1\n  Option::None => 0\n}"),
+      vec!("none: Option<Integer>", "some: Option<Integer>", "r: Result<Decimal, String>", "n: Integer"));
+  }
+
+  #[test]
+  #[should_panic(expected = "Argument 1 of [Option::Some] expected [Integer], found [String]")]
+  fn variant_values_match_the_type_arguments() {
+    check("Option<Integer> o = Option::Some(\"one\")");
+  }
+
+  #[test]
+  #[should_panic(expected = "[Option] takes 1 type argument, found 0")]
+  fn generic_enums_need_their_type_arguments() {
+    check("Option o = Option::None");
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot infer the whole type of [o] from [Option<_>], declare it")]
+  fn a_bare_none_cannot_be_inferred() {
+    check("let o = Option::None");
+  }
+
+  #[test]
+  #[should_panic(expected = "`when` on [Shape] does not handle [Shape::Dot]")]
+  fn when_names_every_variant() {
+    check("enum Shape { Circle(Decimal), Dot }\nShape s = Shape::Dot\nwhen (s) {\n  Shape::Circle(r) => r\n}");
+  }
+
+  #[test]
+  fn a_wildcard_covers_the_other_variants() {
+    assert_eq!(check("enum Shape { Circle(Decimal), Dot }\nShape s = Shape::Dot\nlet r = when (s) {\n  Shape::Circle(r) => r\n  _ => 0.0\n}"),
+      vec!("s: Shape", "r: Decimal"));
+  }
+
+  #[test]
+  #[should_panic(expected = "[f] returns [Integer] but can reach its end without a `return`")]
+  fn functions_must_return_their_value() {
+    check("funk f() Integer { }");
+  }
+
+  #[test]
+  #[should_panic(expected = "[f] returns [Integer] but can reach its end without a `return`")]
+  fn a_try_returns_when_its_catches_do() {
+    check("funk f() Integer {\n  try {\n    return 1\n  } catch (Error e) {\n    println(e.message)\n  }\n}");
+  }
+
+  #[test]
+  fn returning_or_throwing_on_every_path_is_enough() {
+    check("funk f() Integer {\n  try {\n    return 1\n  } catch (Error e) {\n    throw e\n  }\n}\nfunk g(Option<Integer> o) Integer {\n  when (o) {\n    Option::Some(n) => { return n }\n    _ => { return 0 }\n  }\n}");
+  }
+
+  #[test]
+  fn integer_literals_widen_to_big_integer() {
+    assert_eq!(check("mut BigInteger x = 5\nfunk f(BigInteger b) BigInteger {\n  return 1\n}\nlet y = f(2)\nx = 3"), vec!("x: BigInteger", "b: BigInteger", "y: BigInteger"));
+  }
+
+  #[test]
+  #[should_panic(expected = "[x] expected [BigInteger], found [Integer]")]
+  fn only_literals_widen() {
+    check("Integer i = 5\nBigInteger x = i");
+  }
+
+  #[test]
+  fn mut_declarations_change() {
+    assert_eq!(check("mut Integer x = 1\nx += 2\nmut y = 1.5\ny++\nklass P { mut Integer n; Integer fixed }\nP p = P(1, 2)\np.n *= 2"),
+      vec!("x: Integer", "y: Decimal", "p: P"));
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot assign to [x], declare it with `mut` to change it")]
+  fn variables_are_fixed_without_mut() {
+    check("Integer x = 1\nx = 2");
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot assign to [x], declare it with `mut` to change it")]
+  fn let_is_fixed_too() {
+    check("let x = 1\nx++");
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot assign to [a], declare it with `mut` to change it")]
+  fn parameters_never_change() {
+    check("funk f(Integer a) {\n  a -= 1\n}");
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot assign to field [P.n], declare it with `mut` to change it")]
+  fn fields_are_fixed_without_mut() {
+    check("klass P { Integer n }\nP p = P(1)\np.n = 2");
+  }
+
+  #[test]
+  fn array_elements() {
+    assert_eq!(check("mut Array<String> xs = \"a,b\".split(\",\")\nxs[0] = \"c\"\nlet first = xs[1]\nklass Box { mut Array<Integer> ns }\n\
+      Box b = Box(json::parse(\"[1]\") as Array<Integer>)\nb.ns[0] += 1\nlet n = b.ns[0]--"),
+      vec!("xs: Array<String>", "first: String", "b: Box", "n: Integer"));
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot assign to [xs], declare it with `mut` to change it")]
+  fn elements_change_with_their_array() {
+    check("Array<String> xs = \"a,b\".split(\",\")\nxs[0] = \"c\"");
+  }
+
+  #[test]
+  #[should_panic(expected = "Index expected [Integer], found [String]")]
+  fn indices_are_integers() {
+    check("let xs = \"a,b\".split(\",\")\nlet x = xs[\"0\"]");
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot index [String], only an Array")]
+  fn only_arrays_are_indexed() {
+    check("let c = \"abc\"[0]");
+  }
+
+  const SHAPE: &str = "interface Shape {\n  funk area() Decimal\n  funk scale(Decimal by) Shape\n}\n";
+
+  #[test]
+  fn klasses_stand_in_for_their_interfaces() {
+    let code = SHAPE.to_string() + "klass Dot implements Shape {\n  funk area() Decimal { return 0.0 }\n  funk scale(Decimal by) Shape { return Dot() }\n}\n\
+      funk total(Shape a, Shape b) Decimal { return a.area() + b.area() }\nShape s = Dot()\nlet t = total(s, Dot().scale(2.0))";
+    assert_eq!(check(&code), vec!("by: Decimal", "a: Shape", "b: Shape", "s: Shape", "t: Decimal"));
+  }
+
+  #[test]
+  #[should_panic(expected = "Klass [Dot] is missing [funk scale(Decimal) Shape] from interface [Shape]")]
+  fn klasses_provide_every_method() {
+    check(&(SHAPE.to_string() + "klass Dot implements Shape {\n  funk area() Decimal { return 0.0 }\n}"));
+  }
+
+  #[test]
+  #[should_panic(expected = "Klass [Dot] has [funk area() Integer] but interface [Shape] requires [funk area() Decimal]")]
+  fn methods_match_the_interface() {
+    check(&(SHAPE.to_string() + "klass Dot implements Shape {\n  funk area() Integer { return 0 }\n  funk scale(Decimal by) Shape { return Dot() }\n}"));
+  }
+
+  #[test]
+  #[should_panic(expected = "Klass [Dot] implements unknown interface [Drawable]")]
+  fn interfaces_exist() {
+    check("klass Dot implements Drawable { Integer x }");
+  }
+
+  #[test]
+  #[should_panic(expected = "[Shape] has no method [perimeter]")]
+  fn only_the_interface_methods_are_callable() {
+    check(&(SHAPE.to_string() + "funk f(Shape s) Decimal { return s.perimeter() }"));
+  }
+
+  #[test]
+  #[should_panic(expected = "Function [area] is already defined")]
+  fn functions_are_defined_once() {
+    check("funk area() Decimal { return 1.0 }\nfunk area() Integer { return 2 }\nlet a = area()");
+  }
+
+  #[test]
+  #[should_panic(expected = "Type [Shape] is already defined")]
+  fn types_are_defined_once() {
+    check(&(SHAPE.to_string() + "enum Shape { Dot }"));
+  }
+
+  #[test]
+  fn let_takes_the_type_of_its_value() {
+    assert_eq!(check("let a = 5\nlet b = a as Decimal\nlet c = \"x\".split(\",\")\nlet d = Option::Some(c)\nInteger? e = null\nlet f = e ?: a"),
+      vec!("a: Integer", "b: Decimal", "c: Array<String>", "d: Option<Array<String>>", "e: Integer?", "f: Integer"));
+  }
+
+  #[test]
+  #[should_panic(expected = "[x] expected [Integer], found [Decimal]")]
+  fn an_inferred_type_is_fixed() {
+    check("mut x = 1\nx = 1.5");
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot infer the type of [x] from null, declare it")]
+  fn null_does_not_say_which_type() {
+    check("let x = null");
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot infer the type of [x] from an untyped value, cast it with `as`")]
+  fn any_does_not_say_which_type() {
+    check("let x = json::parse(\"1\")");
+  }
+
+  #[test]
+  fn lambdas_take_parameter_and_return_types_from_where_they_go() {
+    let code = "funk apply(funk(Integer) Decimal f, Integer x) Decimal { return f(x) }\nlet y = apply(funk(n) { return n as Decimal }, 2)\n\
+      let g = funk(Integer n) { return n * 2 }";
+    assert_eq!(check(code), vec!("f: funk(Integer) Decimal", "x: Integer", "n: Integer", "y: Decimal", "n: Integer", "g: funk(Integer) Integer"));
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot infer the type of lambda parameter [n], give it a type")]
+  fn lambda_parameters_need_a_context() {
+    check("let f = funk(n) { return n }");
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot assign to [count] in a lambda, [count] is captured by value")]
+  fn lambdas_do_not_assign_to_what_they_capture() {
+    check("mut Integer count = 0\nlet inc = funk() Void { count += 1 }");
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot assign to [b.ns[0]] in a lambda, [b] is captured by value")]
+  fn nor_to_its_fields_and_elements() {
+    check("klass Box { mut Array<Integer> ns }\nmut Box b = Box(json::parse(\"[1]\") as Array<Integer>)\nlet f = funk() Void { b.ns[0]++ }");
+  }
+
+  #[test]
+  fn lambdas_assign_to_their_own_variables() {
+    assert_eq!(check("let f = funk(Integer x) Integer {\n  mut y = x\n  let g = funk() Integer {\n    mut z = 1\n    z += 1\n    return z\n  }\n  y += g()\n  return y\n}"),
+      vec!("x: Integer", "y: Integer", "z: Integer", "g: funk() Integer", "f: funk(Integer) Integer"));
+  }
+}
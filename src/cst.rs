@@ -0,0 +1,1210 @@
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+use diagnostic::Diagnostic;
+use lexer::Lexer;
+use parser::Parser;
+use source_map::{FileId, Span};
+use structs::structs::*;
+use structs::structs as ast;
+
+// A lossless syntax tree: every byte of the source, whitespace and comments included, sits in a
+// leaf, so `SourceFile::text` gives back exactly what was parsed. Green nodes only know their
+// kind, text and children and can be shared, red nodes (`SyntaxNode`) add parent and offset.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+  SourceFile,
+  Type,
+  Block,
+  Enum,
+  Variant,
+  When,
+  WhenArm,
+  Pattern,
+  Function,
+  ParamList,
+  Param,
+  Lambda,
+  Klass,
+  Field,
+  Interface,
+  MethodSignature,
+  Return,
+  Let,
+  Assignment,
+  Reassign,
+  ElvisExpr,
+  BinaryExpr,
+  FieldExpr,
+  IndexExpr,
+  MethodCallExpr,
+  CallExpr,
+  ArgList,
+  VariantExpr,
+  NameRef,
+  Literal,
+  ParenExpr,
+  IncrementExpr,
+  CastExpr,
+  Try,
+  Catch,
+  Finally,
+  Throw
+}
+
+impl NodeKind {
+  pub fn is_expr(self) -> bool {
+    [NodeKind::When, NodeKind::Lambda, NodeKind::ElvisExpr, NodeKind::BinaryExpr, NodeKind::FieldExpr, NodeKind::IndexExpr,
+      NodeKind::MethodCallExpr, NodeKind::CallExpr, NodeKind::VariantExpr, NodeKind::NameRef, NodeKind::Literal, NodeKind::ParenExpr,
+      NodeKind::IncrementExpr, NodeKind::CastExpr].contains(&self)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenToken {
+  pub kind: TokenType,
+  pub text: String,
+  pub value: TokenValue
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenNode {
+  pub kind: NodeKind,
+  pub children: Vec<GreenElement>,
+  pub len: usize
+}
+
+impl GreenNode {
+  // Whether this expression names something an assignment can change: a variable, a field or an Array element.
+  pub fn is_place(&self) -> bool {
+    let inner = self.children.iter().find_map(|child| match child {
+      GreenElement::Node(node) if node.kind.is_expr() => Some(node),
+      _ => None
+    });
+    match self.kind {
+      NodeKind::NameRef => true,
+      NodeKind::FieldExpr if self.children.iter().any(|child| matches!(child, GreenElement::Token(token) if token.kind == TokenType::QuestionDot)) => false,
+      NodeKind::FieldExpr | NodeKind::IndexExpr | NodeKind::ParenExpr => inner.is_some_and(|inner| inner.is_place()),
+      _ => false
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GreenElement {
+  Node(Rc<GreenNode>),
+  Token(Rc<GreenToken>)
+}
+
+impl GreenElement {
+  fn len(&self) -> usize {
+    match self {
+      GreenElement::Node(node) => node.len,
+      GreenElement::Token(token) => token.text.len()
+    }
+  }
+}
+
+// Collects the tree bottom up while the parser runs. The parser opens and closes nodes,
+// tokens are pushed as it consumes them and the gaps between them become whitespace leaves.
+pub struct GreenBuilder<'a> {
+  source: &'a str,
+  // End of the last leaf.
+  offset: usize,
+  parents: Vec<(NodeKind, usize)>,
+  children: Vec<GreenElement>
+}
+
+impl<'a> GreenBuilder<'a> {
+  pub fn new(source: &'a str) -> GreenBuilder<'a> {
+    GreenBuilder{ source, offset: 0, parents: vec!(), children: vec!() }
+  }
+
+  pub fn start_node(&mut self, kind: NodeKind) {
+    self.parents.push((kind, self.children.len()));
+  }
+
+  // Where a node could start, for nodes that are only recognised after their first child.
+  pub fn checkpoint(&self) -> usize {
+    self.children.len()
+  }
+
+  pub fn start_node_at(&mut self, checkpoint: usize, kind: NodeKind) {
+    self.parents.push((kind, checkpoint));
+  }
+
+  pub fn finish_node(&mut self) {
+    let (kind, first) = self.parents.pop().expect("No node to finish");
+    let children = self.children.split_off(first);
+    let len = children.iter().map(|child| child.len()).sum();
+    self.children.push(GreenElement::Node(Rc::new(GreenNode{ kind, children, len })));
+  }
+
+  pub fn token(&mut self, token: &Token) {
+    self.whitespace(token.range.start);
+    self.children.push(GreenElement::Token(Rc::new(GreenToken{
+      kind: token.ty.clone(), text: self.source[token.range.clone()].to_string(), value: token.value.clone()
+    })));
+    self.offset = token.range.end;
+  }
+
+  // The node finished last, if nothing came after it yet.
+  pub fn last_node(&self) -> Option<Rc<GreenNode>> {
+    match self.children.last() {
+      Some(GreenElement::Node(node)) => Some(node.clone()),
+      _ => None
+    }
+  }
+
+  // Whatever follows the last token.
+  pub fn rest(&mut self) {
+    self.whitespace(self.source.len());
+  }
+
+  pub fn finish(&mut self) -> SyntaxNode {
+    let root = match self.children.pop() {
+      Some(GreenElement::Node(root)) if self.children.is_empty() => root,
+      _ => panic!("The syntax tree needs exactly one root")
+    };
+    SyntaxNode::new_root(root)
+  }
+
+  // A leaf for the gap up to `end`, if there is one.
+  pub fn whitespace(&mut self, end: usize) {
+    if end > self.offset {
+      self.children.push(GreenElement::Token(Rc::new(GreenToken{
+        kind: TokenType::Whitespace, text: self.source[self.offset..end].to_string(), value: TokenValue::None
+      })));
+      self.offset = end;
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+  green: Rc<GreenNode>,
+  parent: Option<SyntaxNode>,
+  // Position among the parent's children, trivia included.
+  index: usize,
+  offset: usize
+}
+
+#[derive(Clone)]
+pub struct SyntaxToken {
+  pub green: Rc<GreenToken>
+}
+
+#[derive(Clone)]
+pub enum SyntaxElement {
+  Node(SyntaxNode),
+  Token(SyntaxToken)
+}
+
+impl SyntaxNode {
+  pub fn new_root(green: Rc<GreenNode>) -> SyntaxNode {
+    SyntaxNode(Rc::new(NodeData{ green, parent: None, index: 0, offset: 0 }))
+  }
+
+  pub fn kind(&self) -> NodeKind {
+    self.0.green.kind
+  }
+
+  pub fn parent(&self) -> Option<SyntaxNode> {
+    self.0.parent.clone()
+  }
+
+  // Byte range in the source the tree was built from.
+  pub fn range(&self) -> Range<usize> {
+    self.0.offset..self.0.offset + self.0.green.len
+  }
+
+  // Exactly the source it was parsed from, only tooling and tests look at it.
+  #[allow(dead_code)]
+  pub fn text(&self) -> String {
+    self.tokens().iter().map(|token| token.text()).collect()
+  }
+
+  pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+    let mut offset = self.0.offset;
+    let mut elements = vec!();
+    for (index, child) in self.0.green.children.iter().enumerate() {
+      elements.push(match child {
+        GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData{ green: green.clone(), parent: Some(self.clone()), index, offset }))),
+        GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken{ green: green.clone() })
+      });
+      offset += child.len();
+    }
+    elements
+  }
+
+  pub fn children(&self) -> Vec<SyntaxNode> {
+    self.children_with_tokens().into_iter().filter_map(|element| match element {
+      SyntaxElement::Node(node) => Some(node),
+      SyntaxElement::Token(_) => None
+    }).collect()
+  }
+
+  // Direct child tokens, trivia included.
+  pub fn child_tokens(&self) -> Vec<SyntaxToken> {
+    self.children_with_tokens().into_iter().filter_map(|element| match element {
+      SyntaxElement::Token(token) => Some(token),
+      SyntaxElement::Node(_) => None
+    }).collect()
+  }
+
+  // Every leaf below this node in source order.
+  pub fn tokens(&self) -> Vec<SyntaxToken> {
+    let mut tokens = vec!();
+    for element in self.children_with_tokens() {
+      match element {
+        SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+        SyntaxElement::Token(token) => tokens.push(token)
+      }
+    }
+    tokens
+  }
+
+  fn child<T: CstNode>(&self) -> Option<T> {
+    self.children().into_iter().find_map(T::cast)
+  }
+
+  fn children_of<T: CstNode>(&self) -> Vec<T> {
+    self.children().into_iter().filter_map(T::cast).collect()
+  }
+
+  fn exprs(&self) -> Vec<Expression> {
+    self.children().into_iter().filter_map(Expression::cast).collect()
+  }
+
+  fn token_of(&self, kind: TokenType) -> Option<SyntaxToken> {
+    self.child_tokens().into_iter().find(|token| token.kind() == kind)
+  }
+
+  fn names(&self) -> Vec<String> {
+    self.child_tokens().into_iter().filter(|token| token.kind() == TokenType::Identifier).map(|token| token.text()).collect()
+  }
+
+  fn name(&self) -> Option<String> {
+    self.names().into_iter().next()
+  }
+
+  // Doc comments right before this node, with nothing but newlines in between. Only the leaves in front
+  // of it are looked at, so asking every declaration of a long file stays linear.
+  pub fn doc_comment(&self) -> Option<String> {
+    let parent = self.parent()?;
+    let mut lines = vec!();
+    for element in parent.0.green.children[..self.0.index].iter().rev() {
+      match element {
+        GreenElement::Token(token) if matches!(token.kind, TokenType::Whitespace | TokenType::Newline) => {},
+        GreenElement::Token(token) if token.kind == TokenType::Comment(CommentKind::Doc) => match &token.value {
+          TokenValue::String(text) => lines.push(text.clone()),
+          _ => unreachable!()
+        },
+        _ => break
+      }
+    }
+    lines.reverse();
+    if lines.is_empty() { None } else { Some(lines.join("\n")) }
+  }
+}
+
+impl fmt::Debug for SyntaxNode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{:?}@{:?}", self.kind(), self.range())
+  }
+}
+
+impl SyntaxToken {
+  pub fn kind(&self) -> TokenType {
+    self.green.kind.clone()
+  }
+
+  pub fn text(&self) -> String {
+    self.green.text.clone()
+  }
+
+  // Whitespace and comments.
+  pub fn is_trivia(&self) -> bool {
+    matches!(self.green.kind, TokenType::Whitespace | TokenType::Comment(_))
+  }
+}
+
+// Typed views over a `SyntaxNode` of one kind.
+pub trait CstNode: Sized {
+  fn cast(node: SyntaxNode) -> Option<Self>;
+  fn syntax(&self) -> &SyntaxNode;
+}
+
+macro_rules! cst_node {
+  ($name:ident) => {
+    #[derive(Debug, Clone)]
+    pub struct $name(SyntaxNode);
+
+    impl CstNode for $name {
+      fn cast(node: SyntaxNode) -> Option<$name> {
+        if node.kind() == NodeKind::$name { Some($name(node)) } else { None }
+      }
+
+      fn syntax(&self) -> &SyntaxNode {
+        &self.0
+      }
+    }
+  };
+}
+
+cst_node!(SourceFile);
+cst_node!(Type);
+cst_node!(Block);
+cst_node!(Enum);
+cst_node!(Variant);
+cst_node!(When);
+cst_node!(WhenArm);
+cst_node!(Pattern);
+cst_node!(Function);
+cst_node!(ParamList);
+cst_node!(Param);
+cst_node!(Lambda);
+cst_node!(Klass);
+cst_node!(Field);
+cst_node!(Interface);
+cst_node!(MethodSignature);
+cst_node!(Return);
+cst_node!(Let);
+cst_node!(Assignment);
+cst_node!(Reassign);
+cst_node!(ElvisExpr);
+cst_node!(BinaryExpr);
+cst_node!(FieldExpr);
+cst_node!(IndexExpr);
+cst_node!(MethodCallExpr);
+cst_node!(CallExpr);
+cst_node!(ArgList);
+cst_node!(VariantExpr);
+cst_node!(NameRef);
+cst_node!(Literal);
+cst_node!(ParenExpr);
+cst_node!(IncrementExpr);
+cst_node!(CastExpr);
+cst_node!(Try);
+cst_node!(Catch);
+cst_node!(Finally);
+cst_node!(Throw);
+
+// Any node that produces a value.
+#[derive(Debug, Clone)]
+pub struct Expression(SyntaxNode);
+
+impl CstNode for Expression {
+  fn cast(node: SyntaxNode) -> Option<Expression> {
+    if node.kind().is_expr() { Some(Expression(node)) } else { None }
+  }
+
+  fn syntax(&self) -> &SyntaxNode {
+    &self.0
+  }
+}
+
+impl SourceFile {
+  pub fn parse(source: &str, file: FileId) -> Result<SourceFile, Diagnostic> {
+    let mut parser = Parser::new(Box::new(Lexer::new(source)), source, file);
+    Ok(SourceFile(parser.parse()?))
+  }
+
+  #[allow(dead_code)]
+  pub fn text(&self) -> String {
+    self.0.text()
+  }
+
+  // Top level declarations and expressions, in order.
+  pub fn items(&self) -> Vec<SyntaxNode> {
+    self.0.children()
+  }
+
+  // Lowers the tree to the AST the checker and interpreter run. A tree the parser built has every part
+  // the grammar requires, one put together some other way gets an error where a part is missing.
+  pub fn to_ast(&self, file: FileId) -> Result<Vec<AST>, Diagnostic> {
+    let lower = Lower{ file };
+    self.items().iter().map(|item| lower.statement(item)).collect()
+  }
+}
+
+struct Lower {
+  file: FileId
+}
+
+impl Lower {
+  fn span(&self, node: &SyntaxNode) -> Span {
+    Span{ file: self.file, range: node.range() }
+  }
+
+  // A part of `node` the grammar requires.
+  fn part<T>(&self, part: Option<T>, node: &SyntaxNode, what: &str) -> Result<T, Diagnostic> {
+    part.ok_or_else(|| Diagnostic::error(format!("[{:?}] is missing {}", node.kind(), what), self.span(node)))
+  }
+
+  // The expression the grammar requires in `node`, lowered.
+  fn required(&self, expr: Option<Expression>, node: &SyntaxNode, what: &str) -> Result<Box<AST>, Diagnostic> {
+    Ok(Box::new(self.expr(&self.part(expr, node, what)?)?))
+  }
+
+  fn statement(&self, node: &SyntaxNode) -> Result<AST, Diagnostic> {
+    let span = self.span(node);
+    let statement = match node.kind() {
+      NodeKind::Function => return Ok(AST::Statement(ast::Statement::Function(self.function(&Function(node.clone()))?), span)),
+      NodeKind::Enum => ast::Statement::Enum(self.enum_decl(&Enum(node.clone()))?),
+      NodeKind::Klass => ast::Statement::Klass(self.klass(&Klass(node.clone()))?),
+      NodeKind::Interface => ast::Statement::Interface(self.interface(&Interface(node.clone()))?),
+      NodeKind::Return => ast::Statement::Return(match Return(node.clone()).value() {
+        Some(value) => Some(Box::new(self.expr(&value)?)),
+        None => None
+      }),
+      NodeKind::Throw => ast::Statement::Throw(self.required(Throw(node.clone()).value(), node, "a value")?),
+      NodeKind::Try => ast::Statement::Try(self.try_(&Try(node.clone()))?),
+      NodeKind::Let => {
+        let decl = Let(node.clone());
+        ast::Statement::Assignment(Assign{ name: self.part(decl.name(), node, "a name")?, ty: ast::Type::Infer,
+          value: self.required(decl.value(), node, "a value")?, mutable: decl.is_mutable(), doc: node.doc_comment() })
+      },
+      NodeKind::Assignment => {
+        let decl = Assignment(node.clone());
+        ast::Statement::Assignment(Assign{ name: self.part(decl.name(), node, "a name")?, ty: self.ty(&self.part(decl.ty(), node, "a type")?)?,
+          value: self.required(decl.value(), node, "a value")?, mutable: decl.is_mutable(), doc: node.doc_comment() })
+      },
+      NodeKind::Reassign => {
+        let reassign = Reassign(node.clone());
+        ast::Statement::Reassign{ target: self.required(reassign.target(), node, "a target")?, op: reassign.op(),
+          value: self.required(reassign.value(), node, "a value")? }
+      },
+      kind if kind.is_expr() => return self.expr(&Expression(node.clone())),
+      kind => return Err(Diagnostic::error(format!("Expected a statement, instead found [{:?}]", kind), span))
+    };
+    Ok(AST::Statement(statement, span))
+  }
+
+  fn expr(&self, expr: &Expression) -> Result<AST, Diagnostic> {
+    let node = expr.syntax();
+    let expr = match node.kind() {
+      NodeKind::Literal => match Literal(node.clone()).value() {
+        TokenValue::Integer(value) => Expr::Integer(value),
+        TokenValue::BigInteger(value) => Expr::BigInteger(value),
+        TokenValue::Decimal(value) => Expr::Decimal(value),
+        TokenValue::String(value) => Expr::String(value),
+        _ => Expr::Null
+      },
+      NodeKind::NameRef => Expr::Identifier(self.part(NameRef(node.clone()).name(), node, "a name")?),
+      // The parentheses only group, the value is the expression inside.
+      NodeKind::ParenExpr => return self.expr(&self.part(ParenExpr(node.clone()).expr(), node, "an expression")?),
+      NodeKind::BinaryExpr => {
+        let binary = BinaryExpr(node.clone());
+        Expr::Binary(ast::BinaryExpr{ left: self.required(binary.lhs(), node, "a left operand")?, op: self.part(binary.op(), node, "an operator")?,
+          right: self.required(binary.rhs(), node, "a right operand")? })
+      },
+      NodeKind::ElvisExpr => {
+        let elvis = ElvisExpr(node.clone());
+        Expr::Elvis{ left: self.required(elvis.lhs(), node, "a left operand")?, right: self.required(elvis.rhs(), node, "a right operand")? }
+      },
+      NodeKind::CallExpr => {
+        let call = CallExpr(node.clone());
+        Expr::Call{ name: self.part(call.name(), node, "a name")?, args: self.args(&self.part(call.arg_list(), node, "arguments")?)? }
+      },
+      NodeKind::FieldExpr => {
+        let field = FieldExpr(node.clone());
+        Expr::Field{ object: self.required(field.object(), node, "an object")?, field: self.part(field.field(), node, "a field name")?,
+          safe: field.is_safe() }
+      },
+      NodeKind::IndexExpr => {
+        let index = IndexExpr(node.clone());
+        Expr::Index{ object: self.required(index.object(), node, "an Array")?, index: self.required(index.index(), node, "an index")? }
+      },
+      NodeKind::MethodCallExpr => {
+        let call = MethodCallExpr(node.clone());
+        Expr::MethodCall{ object: self.required(call.object(), node, "an object")?, method: self.part(call.method(), node, "a method name")?,
+          args: self.args(&self.part(call.arg_list(), node, "arguments")?)?, safe: call.is_safe() }
+      },
+      NodeKind::VariantExpr => {
+        let variant = VariantExpr(node.clone());
+        let args = match variant.arg_list() {
+          Some(list) => self.args(&list)?,
+          None => vec!()
+        };
+        Expr::Variant{ enum_name: self.part(variant.enum_name(), node, "an enum name")?, variant: self.part(variant.variant(), node, "a variant name")?,
+          args }
+      },
+      NodeKind::IncrementExpr => {
+        let increment = IncrementExpr(node.clone());
+        Expr::Increment{ target: self.required(increment.target(), node, "a target")?, op: self.part(increment.op(), node, "an operator")?,
+          prefix: increment.is_prefix() }
+      },
+      NodeKind::CastExpr => {
+        let cast = CastExpr(node.clone());
+        Expr::Cast{ value: self.required(cast.expr(), node, "a value")?, ty: self.ty(&self.part(cast.ty(), node, "a type")?)? }
+      },
+      NodeKind::Lambda => {
+        let lambda = Lambda(node.clone());
+        let return_type = match lambda.return_type() {
+          Some(ty) => self.ty(&ty)?,
+          None => ast::Type::Infer
+        };
+        Expr::Lambda(FunctionDecl{ name: "<lambda>".to_string(), params: self.params(&self.part(lambda.param_list(), node, "parameters")?)?,
+          return_type, body: self.block(&self.part(lambda.body(), node, "a body")?)?, doc: None, span: self.span(node) })
+      },
+      NodeKind::When => Expr::When(self.when(&When(node.clone()))?),
+      kind => return Err(Diagnostic::error(format!("Expected an expression, instead found [{:?}]", kind), self.span(node)))
+    };
+    Ok(AST::Expression(expr, self.span(node)))
+  }
+
+  fn args(&self, list: &ArgList) -> Result<Vec<AST>, Diagnostic> {
+    list.args().iter().map(|arg| self.expr(arg)).collect()
+  }
+
+  fn block(&self, block: &Block) -> Result<Vec<AST>, Diagnostic> {
+    block.statements().iter().map(|statement| self.statement(statement)).collect()
+  }
+
+  // Also where type arguments are counted, `Hash<String>` is written fine but means nothing.
+  fn ty(&self, ty: &Type) -> Result<ast::Type, Diagnostic> {
+    let expr = self.part(ty.to_type_expr(), ty.syntax(), "a name")?;
+    expr.to_type().map_err(|message| Diagnostic::error(message, self.span(ty.syntax())))
+  }
+
+  // None when left out, which means Void.
+  fn return_type(&self, ty: Option<Type>) -> Result<ast::Type, Diagnostic> {
+    match ty {
+      Some(ty) => self.ty(&ty),
+      None => Ok(ast::Type::Void)
+    }
+  }
+
+  // Expr::Argument, typed Infer when a lambda leaves the type out.
+  fn params(&self, list: &ParamList) -> Result<Vec<AST>, Diagnostic> {
+    list.params().iter().map(|param| {
+      let ty = match param.ty() {
+        Some(ty) => self.ty(&ty)?,
+        None => ast::Type::Infer
+      };
+      Ok(AST::Expression(Expr::Argument{ name: self.part(param.name(), param.syntax(), "a name")?, ty }, self.span(param.syntax())))
+    }).collect()
+  }
+
+  fn function(&self, function: &Function) -> Result<FunctionDecl, Diagnostic> {
+    let node = function.syntax();
+    Ok(FunctionDecl{ name: self.part(function.name(), node, "a name")?, params: self.params(&self.part(function.param_list(), node, "parameters")?)?,
+      return_type: self.return_type(function.return_type())?, body: self.block(&self.part(function.body(), node, "a body")?)?,
+      doc: node.doc_comment(), span: self.span(node) })
+  }
+
+  fn enum_decl(&self, decl: &Enum) -> Result<EnumDecl, Diagnostic> {
+    let variants = decl.variants().iter().map(|variant| Ok(EnumVariant{ name: self.part(variant.name(), variant.syntax(), "a name")?,
+      fields: variant.fields().iter().map(|ty| self.ty(ty)).collect::<Result<Vec<ast::Type>, Diagnostic>>()? }))
+      .collect::<Result<Vec<EnumVariant>, Diagnostic>>()?;
+    Ok(EnumDecl{ name: self.part(decl.name(), decl.syntax(), "a name")?, params: vec!(), variants, doc: decl.syntax().doc_comment() })
+  }
+
+  fn klass(&self, klass: &Klass) -> Result<KlassDecl, Diagnostic> {
+    let mut fields = vec!();
+    let mut mutable = vec!();
+    for field in klass.fields() {
+      let name = self.part(field.name(), field.syntax(), "a name")?;
+      if field.is_mutable() {
+        mutable.push(name.clone());
+      }
+      let ty = self.ty(&self.part(field.ty(), field.syntax(), "a type")?)?;
+      fields.push(AST::Expression(Expr::Argument{ name, ty }, self.span(field.syntax())));
+    }
+    let methods = klass.methods().iter().map(|method| self.function(method)).collect::<Result<Vec<FunctionDecl>, Diagnostic>>()?;
+    Ok(KlassDecl{ name: self.part(klass.name(), klass.syntax(), "a name")?, interfaces: klass.interfaces(), fields, mutable, methods,
+      doc: klass.syntax().doc_comment() })
+  }
+
+  fn interface(&self, interface: &Interface) -> Result<InterfaceDecl, Diagnostic> {
+    let methods = interface.methods().iter().map(|method| {
+      let params = self.params(&self.part(method.param_list(), method.syntax(), "parameters")?)?;
+      Ok(ast::MethodSignature{ name: self.part(method.name(), method.syntax(), "a name")?, params: params.iter().map(|param| param.argument().1).collect(),
+        return_type: self.return_type(method.return_type())? })
+    }).collect::<Result<Vec<ast::MethodSignature>, Diagnostic>>()?;
+    Ok(InterfaceDecl{ name: self.part(interface.name(), interface.syntax(), "a name")?, methods, doc: interface.syntax().doc_comment() })
+  }
+
+  fn when(&self, when: &When) -> Result<ast::When, Diagnostic> {
+    let arms = when.arms().iter().map(|arm| {
+      let pattern = self.part(arm.pattern(), arm.syntax(), "a pattern")?;
+      let pattern = if pattern.is_wildcard() {
+        ast::Pattern::Wildcard
+      } else {
+        ast::Pattern::Variant{ enum_name: self.part(pattern.enum_name(), pattern.syntax(), "an enum name")?,
+          variant: self.part(pattern.variant(), pattern.syntax(), "a variant name")?, bindings: pattern.bindings() }
+      };
+      // A block, or a single statement on its own.
+      let body = self.part(arm.body(), arm.syntax(), "a body")?;
+      let body = match Block::cast(body.clone()) {
+        Some(block) => self.block(&block)?,
+        None => vec!(self.statement(&body)?)
+      };
+      Ok(ast::WhenArm{ pattern, body })
+    }).collect::<Result<Vec<ast::WhenArm>, Diagnostic>>()?;
+    Ok(ast::When{ subject: self.required(when.subject(), when.syntax(), "a subject")?, arms })
+  }
+
+  fn try_(&self, try_: &Try) -> Result<ast::Try, Diagnostic> {
+    let catches = try_.catches().iter().map(|catch| Ok(ast::Catch{ ty: self.ty(&self.part(catch.ty(), catch.syntax(), "a type")?)?,
+      name: self.part(catch.name(), catch.syntax(), "a name")?, body: self.block(&self.part(catch.body(), catch.syntax(), "a body")?)? }))
+      .collect::<Result<Vec<ast::Catch>, Diagnostic>>()?;
+    let finally = match try_.finally() {
+      Some(finally) => Some(self.block(&self.part(finally.body(), finally.syntax(), "a body")?)?),
+      None => None
+    };
+    Ok(ast::Try{ body: self.block(&self.part(try_.body(), try_.syntax(), "a body")?)?, catches, finally })
+  }
+}
+
+impl Type {
+  // As written, `Integer?` or `funk(Integer) Decimal`.
+  #[allow(dead_code)]
+  pub fn text(&self) -> String {
+    self.0.text()
+  }
+
+  pub fn is_nullable(&self) -> bool {
+    self.0.token_of(TokenType::Question).is_some()
+  }
+
+  pub fn is_function(&self) -> bool {
+    self.0.token_of(TokenType::Keyword(Keyword::Funk)).is_some()
+  }
+
+  // Parameter types of a function type.
+  pub fn params(&self) -> Vec<Type> {
+    let mut types = self.0.children_of::<Type>();
+    types.pop();
+    types
+  }
+
+  // Type arguments, `String` and `Integer` in `Hash<String, Integer>`.
+  pub fn args(&self) -> Vec<Type> {
+    if self.is_function() { vec!() } else { self.0.children_of() }
+  }
+
+  // None when a part is missing, a function type without its return type or a type without a name.
+  pub fn to_type_expr(&self) -> Option<TypeExpr> {
+    if self.is_function() {
+      let ret = self.0.children_of::<Type>().pop()?;
+      let params = self.params().iter().map(Type::to_type_expr).collect::<Option<Vec<TypeExpr>>>()?;
+      return Some(TypeExpr::Function(params, Box::new(ret.to_type_expr()?)));
+    }
+    // The first token names the type, a keyword for the builtins.
+    let name = self.0.child_tokens().into_iter().find(|token| ! token.is_trivia())?.text();
+    let ty = TypeExpr::Name(name, self.args().iter().map(Type::to_type_expr).collect::<Option<Vec<TypeExpr>>>()?);
+    Some(if self.is_nullable() { TypeExpr::Nullable(Box::new(ty)) } else { ty })
+  }
+}
+
+impl Block {
+  pub fn statements(&self) -> Vec<SyntaxNode> {
+    self.0.children()
+  }
+}
+
+impl Enum {
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn variants(&self) -> Vec<Variant> {
+    self.0.children_of()
+  }
+}
+
+impl Variant {
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn fields(&self) -> Vec<Type> {
+    self.0.children_of()
+  }
+}
+
+impl When {
+  pub fn subject(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+
+  pub fn arms(&self) -> Vec<WhenArm> {
+    self.0.children_of()
+  }
+}
+
+impl WhenArm {
+  pub fn pattern(&self) -> Option<Pattern> {
+    self.0.child()
+  }
+
+  // Either a block or a single statement.
+  pub fn body(&self) -> Option<SyntaxNode> {
+    self.0.children().into_iter().find(|node| node.kind() != NodeKind::Pattern)
+  }
+}
+
+impl Pattern {
+  pub fn is_wildcard(&self) -> bool {
+    self.0.token_of(TokenType::ColonColon).is_none()
+  }
+
+  pub fn enum_name(&self) -> Option<String> {
+    if self.is_wildcard() { None } else { self.0.name() }
+  }
+
+  pub fn variant(&self) -> Option<String> {
+    if self.is_wildcard() { None } else { self.0.names().get(1).cloned() }
+  }
+
+  pub fn bindings(&self) -> Vec<String> {
+    self.0.names().into_iter().skip(2).collect()
+  }
+}
+
+impl Function {
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn param_list(&self) -> Option<ParamList> {
+    self.0.child()
+  }
+
+  // None when left out, which means Void.
+  pub fn return_type(&self) -> Option<Type> {
+    self.0.child()
+  }
+
+  pub fn body(&self) -> Option<Block> {
+    self.0.child()
+  }
+}
+
+impl ParamList {
+  pub fn params(&self) -> Vec<Param> {
+    self.0.children_of()
+  }
+}
+
+impl Param {
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  // None for lambda parameters left to the checker.
+  pub fn ty(&self) -> Option<Type> {
+    self.0.child()
+  }
+}
+
+impl Lambda {
+  pub fn param_list(&self) -> Option<ParamList> {
+    self.0.child()
+  }
+
+  pub fn return_type(&self) -> Option<Type> {
+    self.0.child()
+  }
+
+  pub fn body(&self) -> Option<Block> {
+    self.0.child()
+  }
+}
+
+impl Klass {
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn interfaces(&self) -> Vec<String> {
+    self.0.names().into_iter().skip(1).collect()
+  }
+
+  pub fn fields(&self) -> Vec<Field> {
+    self.0.children_of()
+  }
+
+  pub fn methods(&self) -> Vec<Function> {
+    self.0.children_of()
+  }
+}
+
+impl Field {
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn is_mutable(&self) -> bool {
+    self.0.token_of(TokenType::Keyword(Keyword::Mut)).is_some()
+  }
+
+  pub fn ty(&self) -> Option<Type> {
+    self.0.child()
+  }
+}
+
+impl Interface {
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn methods(&self) -> Vec<MethodSignature> {
+    self.0.children_of()
+  }
+}
+
+impl MethodSignature {
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn param_list(&self) -> Option<ParamList> {
+    self.0.child()
+  }
+
+  pub fn return_type(&self) -> Option<Type> {
+    self.0.child()
+  }
+}
+
+impl Return {
+  pub fn value(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+}
+
+// `let x = 1`, or `mut x = 1` when it can change.
+impl Let {
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn is_mutable(&self) -> bool {
+    self.0.token_of(TokenType::Keyword(Keyword::Mut)).is_some()
+  }
+
+  pub fn value(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+}
+
+impl Assignment {
+  pub fn ty(&self) -> Option<Type> {
+    self.0.child()
+  }
+
+  pub fn is_mutable(&self) -> bool {
+    self.0.token_of(TokenType::Keyword(Keyword::Mut)).is_some()
+  }
+
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn value(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+}
+
+impl Reassign {
+  pub fn target(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+
+  // The operator of `+=` and friends, None for a plain `=`.
+  pub fn op(&self) -> Option<BinOp> {
+    self.0.child_tokens().into_iter().find(|token| ! token.is_trivia()).and_then(|token| BinOp::compound(&token.kind()))
+  }
+
+  pub fn value(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().nth(1)
+  }
+}
+
+impl ElvisExpr {
+  pub fn lhs(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+
+  pub fn rhs(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().nth(1)
+  }
+}
+
+impl BinaryExpr {
+  pub fn lhs(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+
+  pub fn op(&self) -> Option<BinOp> {
+    self.0.child_tokens().into_iter().find(|token| ! token.is_trivia()).map(|token| BinOp::new(&token.kind()))
+  }
+
+  pub fn rhs(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().nth(1)
+  }
+}
+
+impl FieldExpr {
+  pub fn object(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+
+  pub fn field(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  // Written with `?.`.
+  pub fn is_safe(&self) -> bool {
+    self.0.token_of(TokenType::QuestionDot).is_some()
+  }
+}
+
+impl IndexExpr {
+  pub fn object(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+
+  pub fn index(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().nth(1)
+  }
+}
+
+impl MethodCallExpr {
+  pub fn object(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+
+  pub fn method(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn arg_list(&self) -> Option<ArgList> {
+    self.0.child()
+  }
+
+  pub fn is_safe(&self) -> bool {
+    self.0.token_of(TokenType::QuestionDot).is_some()
+  }
+}
+
+impl CallExpr {
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn arg_list(&self) -> Option<ArgList> {
+    self.0.child()
+  }
+}
+
+impl ArgList {
+  pub fn args(&self) -> Vec<Expression> {
+    self.0.exprs()
+  }
+}
+
+impl VariantExpr {
+  pub fn enum_name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn variant(&self) -> Option<String> {
+    self.0.names().get(1).cloned()
+  }
+
+  pub fn arg_list(&self) -> Option<ArgList> {
+    self.0.child()
+  }
+}
+
+impl NameRef {
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+}
+
+impl Literal {
+  // The decoded number, or None for `null`.
+  pub fn value(&self) -> TokenValue {
+    self.0.child_tokens().into_iter().find(|token| ! token.is_trivia()).map(|token| token.green.value.clone()).unwrap_or(TokenValue::None)
+  }
+}
+
+impl ParenExpr {
+  pub fn expr(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+}
+
+impl IncrementExpr {
+  pub fn target(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+
+  // Add for `++`, Subtract for `--`.
+  pub fn op(&self) -> Option<BinOp> {
+    self.0.child_tokens().into_iter().find(|token| ! token.is_trivia()).map(|token| match token.kind() {
+      TokenType::PlusPlus => BinOp::Add,
+      _ => BinOp::Subtract
+    })
+  }
+
+  // `++x` rather than `x++`.
+  pub fn is_prefix(&self) -> bool {
+    self.0.children_with_tokens().into_iter().find(|element| ! matches!(element, SyntaxElement::Token(token) if token.is_trivia()))
+      .is_some_and(|element| matches!(element, SyntaxElement::Token(_)))
+  }
+}
+
+impl CastExpr {
+  pub fn expr(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+
+  pub fn ty(&self) -> Option<Type> {
+    self.0.child()
+  }
+}
+
+impl Try {
+  pub fn body(&self) -> Option<Block> {
+    self.0.child()
+  }
+
+  pub fn catches(&self) -> Vec<Catch> {
+    self.0.children_of()
+  }
+
+  pub fn finally(&self) -> Option<Finally> {
+    self.0.child()
+  }
+}
+
+impl Catch {
+  pub fn ty(&self) -> Option<Type> {
+    self.0.child()
+  }
+
+  pub fn name(&self) -> Option<String> {
+    self.0.name()
+  }
+
+  pub fn body(&self) -> Option<Block> {
+    self.0.child()
+  }
+}
+
+impl Finally {
+  pub fn body(&self) -> Option<Block> {
+    self.0.child()
+  }
+}
+
+impl Throw {
+  pub fn value(&self) -> Option<Expression> {
+    self.0.exprs().into_iter().next()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SOURCE: &str = "// Shapes.\n\n/// Doubles.\nfunk double(Integer x) Integer {\n  return x * 2; // twice\n}\n\nInteger? a = double(  4 ) ?: 0\n\nenum Shape { Circle(Decimal), Dot }\nwhen (Shape::Dot) {\n  Shape::Circle(r) => r\n  _ => 0.0\n}\n";
+
+  #[test]
+  fn round_trips_the_source() {
+    let file = SourceFile::parse(SOURCE, FileId(0)).unwrap();
+    assert_eq!(file.text(), SOURCE);
+    assert_eq!(file.syntax().range(), 0..SOURCE.len());
+  }
+
+  #[test]
+  fn typed_accessors() {
+    let file = SourceFile::parse(SOURCE, FileId(0)).unwrap();
+    let double = &Function::cast(file.items()[0].clone()).unwrap();
+    assert_eq!(double.name(), Some("double".to_string()));
+    let params = double.param_list().unwrap().params();
+    assert_eq!((params[0].name(), params[0].ty().unwrap().text()), (Some("x".to_string()), "Integer".to_string()));
+    assert_eq!(double.return_type().unwrap().text(), "Integer");
+
+    let ret = Return::cast(double.body().unwrap().statements()[0].clone()).unwrap();
+    let product = BinaryExpr::cast(ret.value().unwrap().syntax().clone()).unwrap();
+    assert_eq!(product.op(), Some(BinOp::Multiply));
+    assert_eq!(product.syntax().text(), "x * 2");
+
+    let assignment = Assignment::cast(file.items()[1].clone()).unwrap();
+    assert_eq!(assignment.ty().unwrap().text(), "Integer?");
+    let elvis = ElvisExpr::cast(assignment.value().unwrap().syntax().clone()).unwrap();
+    let call = CallExpr::cast(elvis.lhs().unwrap().syntax().clone()).unwrap();
+    assert_eq!(call.syntax().text(), "double(  4 )");
+    assert_eq!(call.arg_list().unwrap().args().len(), 1);
+
+    let shape = Enum::cast(file.items()[2].clone()).unwrap();
+    assert_eq!(shape.variants().iter().map(|v| v.name().unwrap()).collect::<Vec<String>>(), vec!("Circle", "Dot"));
+    let when = When::cast(file.items()[3].clone()).unwrap();
+    let arm = &when.arms()[0];
+    assert_eq!(arm.pattern().unwrap().bindings(), vec!("r"));
+    assert!(when.arms()[1].pattern().unwrap().is_wildcard());
+  }
+
+  #[test]
+  fn doc_comments_come_from_the_leaves_in_front() {
+    let code = "/// One\n/// two\nInteger a = 1\n// plain\nInteger b = 2\n/// Kept\n\n\nklass K {\n  /// Method\n  funk m() { }\n}\n".repeat(500);
+    let file = SourceFile::parse(&code, FileId(0)).unwrap();
+    let docs: Vec<Option<String>> = file.items().iter().map(SyntaxNode::doc_comment).collect();
+    for (i, doc) in docs.chunks(3).enumerate() {
+      assert_eq!(doc, [Some("One\ntwo".to_string()), None, Some("Kept".to_string())], "declarations {}", i * 3);
+    }
+    let klass = Klass::cast(file.items()[2].clone()).unwrap();
+    assert_eq!(klass.methods()[0].syntax().doc_comment(), Some("Method".to_string()));
+  }
+
+  #[test]
+  fn lowers_every_kind_of_node() {
+    let everything = "/// A point.\n// not the doc\n/// Docs.\nklass Point implements Shape { Integer x; funk(Integer) Integer f\n  /// Moves.\n  funk move(Integer by) { x += by }\n}\n\
+      interface Shape { funk area() Decimal; funk scale(Decimal by, Integer times) }\nlet p = Point(1, funk(a) { return a })\n\
+      Array<Hash<String, Integer?>>? xs = null\nfunk(Integer, Decimal) Integer g = funk(Integer a, b) Integer { return a }\n\
+      p.x = ((1 + 2) * 3) % 4; p?.move(2); ++p.x; p.x--\ntry { throw Error(\"no\") } catch (Error e) { e.message } finally { return }\n\
+      let y = xs ?: 0x1F as Decimal as Any\nlet z = Option::Some(\"s\")\nwhen (z) { Option::Some(s) => { s }, _ => Option::None }\n";
+    let mut sources = vec!(SOURCE.to_string(), everything.to_string());
+    for entry in std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/scripts")).unwrap() {
+      let path = entry.unwrap().path();
+      if path.extension().is_some_and(|extension| extension == "fk") {
+        sources.push(std::fs::read_to_string(path).unwrap());
+      }
+    }
+
+    // Scripts that stop at a parse error have nothing to lower.
+    for source in sources.iter().filter(|source| SourceFile::parse(source, FileId(0)).is_ok()) {
+      let file = SourceFile::parse(source, FileId(0)).unwrap();
+      let program = file.to_ast(FileId(0)).unwrap();
+      let items: Vec<Range<usize>> = file.items().iter().map(SyntaxNode::range).collect();
+      assert_eq!(program.iter().map(|ast| ast.span().range.clone()).collect::<Vec<Range<usize>>>(), items, "{}", source);
+    }
+  }
+
+  #[test]
+  fn missing_parts_are_errors() {
+    let token = |ty: TokenType, range: Range<usize>| Token{ ty, value: TokenValue::None, line: 0, column: range.start, utf16_column: range.start, range };
+    let mut builder = GreenBuilder::new("throw; Hash<String> h = 1");
+    builder.start_node(NodeKind::SourceFile);
+    builder.start_node(NodeKind::Throw);
+    builder.token(&token(TokenType::Keyword(Keyword::Throw), 0..5));
+    builder.finish_node();
+    builder.token(&token(TokenType::Semi, 5..6));
+    builder.finish_node();
+    let error = SourceFile(builder.finish()).to_ast(FileId(0)).unwrap_err();
+    assert_eq!((error.message.as_str(), error.span.range), ("[Throw] is missing a value", 0..5));
+
+    let error = SourceFile::parse("Integer a = 1\nHash<String> h = null", FileId(0)).unwrap().to_ast(FileId(0)).unwrap_err();
+    assert_eq!((error.message.as_str(), error.span.range), ("[Hash] takes 2 type arguments, found 1", 14..26));
+  }
+}
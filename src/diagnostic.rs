@@ -0,0 +1,81 @@
+use source_map::{Files, Span};
+
+// A message pointing into the source, rendered as
+//
+// error: Division by zero
+//  --> main.fk:2:10
+//   |
+// 2 |   return a / b
+//   |          ^^^^^
+//   = at divide (main.fk:2:10)
+#[derive(Debug)]
+pub struct Diagnostic {
+  pub message: String,
+  pub span: Span,
+  // Printed under the source excerpt, one per line.
+  pub notes: Vec<String>
+}
+
+impl Diagnostic {
+  pub fn error(message: String, span: Span) -> Diagnostic {
+    Diagnostic{ message, span, notes: vec!() }
+  }
+
+  pub fn note(mut self, note: String) -> Diagnostic {
+    self.notes.push(note);
+    self
+  }
+
+  pub fn render(&self, files: &Files) -> String {
+    let mut out = format!("error: {}", self.message);
+    let map = match files.get(self.span.file) {
+      Some(map) => map,
+      None => {
+        for note in &self.notes {
+          out.push_str(&format!("\n  = {}", note));
+        }
+        return out;
+      }
+    };
+
+    let start = map.position(self.span.range.start);
+    let end = map.position(self.span.range.end);
+    let text = map.line_text(start.line).unwrap_or("");
+    let number = (start.line + 1).to_string();
+    let gutter = " ".repeat(number.len());
+
+    // The underline stops at the end of the first line for spans covering several.
+    let width = if end.line == start.line { end.column.saturating_sub(start.column) } else { text.chars().count().saturating_sub(start.column) };
+    // Tabs stay tabs so the carets line up with the text above them.
+    let indent: String = text.chars().take(start.column).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+
+    out.push_str(&format!("\n{}--> {}", gutter, files.location(&self.span)));
+    out.push_str(&format!("\n{} |", gutter));
+    out.push_str(&format!("\n{} | {}", number, text));
+    out.push_str(&format!("\n{} | {}{}", gutter, indent, "^".repeat(width.max(1))));
+    for note in &self.notes {
+      out.push_str(&format!("\n{} = {}", gutter, note));
+    }
+    out
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn renders_the_line_with_carets_and_notes() {
+    let mut files = Files::new();
+    let file = files.add("main.fk", "funk f() Integer {\n\treturn 1 / 0\n}\n");
+    let diagnostic = Diagnostic::error("Division by zero".to_string(), Span{ file, range: 27..32 }).note("at f (main.fk:2:9)".to_string());
+
+    assert_eq!(diagnostic.render(&files), "error: Division by zero\n --> main.fk:2:9\n  |\n2 | \treturn 1 / 0\n  | \t       ^^^^^\n  = at f (main.fk:2:9)");
+  }
+
+  #[test]
+  fn renders_without_a_source() {
+    let diagnostic = Diagnostic::error("Stopped".to_string(), Span::default()).note("at <top>".to_string());
+    assert_eq!(diagnostic.render(&Files::new()), "error: Stopped\n  = at <top>");
+  }
+}
@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+// An interned name, comparing two of them is comparing two integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+// One table per thread. The lexer interns every identifier and keyword and the parser compares
+// the symbols, the AST lowered from the tree holds Strings, which the checker and interpreter key by.
+// Names are leaked on purpose, they live as long as the program being run.
+struct Interner {
+  symbols: HashMap<&'static str, Symbol>,
+  names: Vec<&'static str>
+}
+
+thread_local! {
+  static INTERNER: RefCell<Interner> = RefCell::new(Interner{ symbols: HashMap::new(), names: vec!() });
+}
+
+impl Symbol {
+  pub fn intern(name: &str) -> Symbol {
+    INTERNER.with(|interner| {
+      let mut interner = interner.borrow_mut();
+      if let Some(symbol) = interner.symbols.get(name) {
+        return *symbol;
+      }
+      let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+      let symbol = Symbol(interner.names.len() as u32);
+      interner.names.push(name);
+      interner.symbols.insert(name, symbol);
+      symbol
+    })
+  }
+
+  pub fn as_str(self) -> &'static str {
+    INTERNER.with(|interner| interner.borrow().names[self.0 as usize])
+  }
+}
+
+impl fmt::Display for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use lexer::Lexer;
+  use structs::structs::{Token, TokenValue};
+  use super::*;
+
+  #[test]
+  fn the_same_name_gives_the_same_symbol() {
+    let first = Symbol::intern("radius");
+    assert_eq!(Symbol::intern("radius"), first);
+    assert_ne!(Symbol::intern("diameter"), first);
+    assert_ne!(Symbol::intern("Radius"), first);
+  }
+
+  #[test]
+  fn symbols_give_back_their_name() {
+    let names = ["x", "", "naïve", "_"];
+    let symbols: Vec<Symbol> = names.iter().map(|name| Symbol::intern(name)).collect();
+    assert_eq!(symbols.iter().map(|symbol| symbol.as_str()).collect::<Vec<&str>>(), names);
+    assert_eq!(symbols[2].to_string(), "naïve");
+  }
+
+  #[test]
+  fn the_lexer_interns_names_and_keywords() {
+    let tokens: Vec<Token> = Lexer::new("let total = total").map(Result::unwrap).collect();
+    let symbols: Vec<Symbol> = tokens.iter().filter_map(|token| match token.value {
+      TokenValue::Symbol(symbol) => Some(symbol),
+      _ => None
+    }).collect();
+    assert_eq!(symbols, vec!(Symbol::intern("let"), Symbol::intern("total"), Symbol::intern("total")));
+  }
+}
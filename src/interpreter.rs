@@ -1,56 +1,850 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use bigint::BigInt;
+use diagnostic::Diagnostic;
+use source_map::{Files, Span};
 use structs::structs::*;
 
+// Deeper recursion is an error rather than a crash of the interpreter's own stack.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
 pub struct Interpreter {
   pub asts: Vec<AST>,
   pub current_ast: Option<AST>,
   pub pos: usize,
-  pub last_pos: usize,
-  pub env: Env
+  pub env: Env,
+  // Set by `return` until the enclosing function call picks it up.
+  pub returning: Option<Object>,
+  pub files: Files,
+  // The node being run, errors point here.
+  pub span: Span,
+  // Calls in progress, innermost last.
+  pub frames: Vec<Frame>
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+  pub function: String,
+  // Where it was called from.
+  pub call: Span
+}
+
+// Why the program stopped, and the calls that led there. A `catch` can stop it first.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+  pub message: String,
+  // The node that failed.
+  pub span: Span,
+  // Innermost first.
+  pub frames: Vec<Frame>,
+  // What `throw` threw, None for errors the interpreter raised itself.
+  pub thrown: Option<Box<Object>>
+}
+
+// What an assignment writes to, with every index already evaluated so `xs[f()] += 1` calls `f` once.
+enum Place {
+  Variable(String),
+  Field(Box<Place>, String),
+  Element(Box<Place>, Box<Object>)
+}
+
+impl RuntimeError {
+  // Innermost first, each function with how far it had got. A run of identical lines, as deep recursion
+  // leaves, shows once with a count.
+  pub fn trace(&self, files: &Files) -> Vec<String> {
+    let mut lines: Vec<(String, usize)> = vec!();
+    let mut at = &self.span;
+    let locations = self.frames.iter().map(|frame| frame.function.as_str()).chain(["<top>"]);
+    for (i, function) in locations.enumerate() {
+      let line = format!("at {} ({})", function, files.location(at));
+      match lines.last_mut() {
+        Some((last, repeats)) if *last == line => *repeats += 1,
+        _ => lines.push((line, 0))
+      }
+      if let Some(frame) = self.frames.get(i) {
+        at = &frame.call;
+      }
+    }
+    lines.into_iter().map(|(line, repeats)| match repeats {
+      0 => line,
+      repeats => format!("{} (and {} more times)", line, repeats)
+    }).collect()
+  }
+
+  pub fn render(&self, files: &Files) -> String {
+    self.trace(files).into_iter().fold(Diagnostic::error(self.message.clone(), self.span.clone()), Diagnostic::note).render(files)
+  }
 }
 
 impl Interpreter {
+  // `natives` is the environment to start from, see `natives::registry`.
+  pub fn new(asts: Vec<AST>, files: Files, natives: Env) -> Interpreter {
+    Interpreter{ asts, current_ast: None, pos: 0, env: natives, returning: None, files, span: Span::default(), frames: vec!() }
+  }
+
+  // An error at the node being run, with the calls that led there.
+  fn error(&self, message: String) -> RuntimeError {
+    RuntimeError{ message, span: self.span.clone(), frames: self.frames.iter().rev().cloned().collect(), thrown: None }
+  }
+
+  // What `throw value` raises. An Error gets the trace from here unless it already has one.
+  fn throw(&mut self, value: Object) -> RuntimeError {
+    let mut error = self.error(String::new());
+    let value = match value {
+      Object::Instance(mut instance) if instance.class == "Error" => {
+        if let Some(Object::Variable(trace)) = instance.env.get_var("trace".to_string()) {
+          if *trace.value == Object::String(Str{ value: String::new() }) {
+            *trace.value = Object::String(Str{ value: error.trace(&self.files).join("\n") });
+          }
+        }
+        if let Some(Object::Variable(Variable{ value, .. })) = instance.env.get_var("message".to_string()) {
+          if let Object::String(message) = &**value {
+            error.message = message.value.clone();
+          }
+        }
+        Object::Instance(instance)
+      },
+      Object::String(message) => {
+        error.message = message.value.clone();
+        Object::String(message)
+      },
+      value => {
+        error.message = format!("Uncaught [{}]", value);
+        value
+      }
+    };
+    error.thrown = Some(Box::new(value));
+    error
+  }
+
+  // The value a `catch` sees: whatever was thrown, or an Error for the interpreter's own failures.
+  fn caught(&self, error: &RuntimeError) -> Object {
+    match &error.thrown {
+      Some(value) => (**value).clone(),
+      None => Object::error(error.message.clone(), error.trace(&self.files).join("\n"))
+    }
+  }
+
   fn next(&mut self) {
-    if self.pos > self.last_pos {
-      self.current_ast = None;
-    } else {
-      self.current_ast = Some(self.asts[self.pos].clone());
+    self.current_ast = self.asts.get(self.pos).cloned();
+    if self.current_ast.is_some() {
       self.pos += 1;
     }
   }
 
-  pub fn interpret(&mut self) {
+  // Runs the top level, then `main` when there is one. Gives the exit code.
+  pub fn interpret(&mut self, args: Vec<String>) -> Result<i32, RuntimeError> {
+    self.define_builtins();
+    // Declarations are visible to the whole program wherever they are written, as the checker has it.
+    for ast in self.asts.clone() {
+      if ast.is_declaration() {
+        self.handle_ast(ast)?;
+      }
+    }
     self.next();
-    while self.current_ast != None {
-      self.handle_ast(self.current_ast.clone().unwrap());
+    while let Some(ast) = self.current_ast.clone() {
+      if ! ast.is_declaration() {
+        self.handle_ast(ast)?;
+      }
       self.next();
     }
+
+    let main = match self.env.get_func("main".to_string()) {
+      Some(Object::Function(main)) => main.clone(),
+      _ => return Ok(0)
+    };
+    let args = Object::Array(args.into_iter().map(|value| Object::String(Str{ value })).collect());
+    match self.call_function(main, vec!(args), None)? {
+      Object::Integer(code) => i32::try_from(code.value).map_err(|_| self.error(format!("Exit code [{}] is out of range", code.value))),
+      other => Err(self.error(format!("[main] should return an Integer, instead returned [{}]", other)))
+    }
   }
 
-  fn handle_ast(&mut self, ast: AST) -> Object {
-    if let AST::Expression(Expr::Binary(binop)) = ast { 
-      let left = self.handle_ast(*binop.left);
-      let right = self.handle_ast(*binop.right);
+  fn define_builtins(&mut self) {
+    for decl in EnumDecl::builtins() {
+      self.env.set_type(decl.name.clone(), Object::Enum(decl));
+    }
+    for decl in KlassDecl::builtins() {
+      let class = Class{ name: decl.name.clone(), interfaces: decl.interfaces, fields: decl.fields, env: Env::new() };
+      self.env.set_type(decl.name, Object::Class(class));
+    }
+  }
 
-      if binop.op == BinOp::Add {
-        let a = left + right;
-        a
-      } else {
-        panic!("")
+  fn handle_ast(&mut self, ast: AST) -> Result<Object, RuntimeError> {
+    let outer = std::mem::replace(&mut self.span, ast.span().clone());
+    let result = self.run_ast(ast);
+    // Errors keep the span they were made with.
+    self.span = outer;
+    result
+  }
+
+  fn run_ast(&mut self, ast: AST) -> Result<Object, RuntimeError> {
+    let object = if let AST::Expression(Expr::Binary(binop), _) = ast { 
+      let left = self.handle_ast(*binop.left)?;
+      let right = self.handle_ast(*binop.right)?;
+
+      match left.arithmetic(binop.op, right) {
+        Ok(result) => result,
+        Err(message) => return Err(self.error(message))
       }
     }
-    else if let AST::Expression(Expr::Integer(val)) = ast {
+    else if let AST::Expression(Expr::Integer(val), _) = ast {
       Object::Integer(Integer{ value: val })
     }
+    else if let AST::Expression(Expr::Decimal(val), _) = ast {
+      Object::Decimal(Decimal{ value: val })
+    }
+    else if let AST::Expression(Expr::BigInteger(val), _) = ast {
+      Object::BigInteger(BigInteger{ value: val })
+    }
+    else if let AST::Expression(Expr::String(value), _) = ast {
+      Object::String(Str{ value })
+    }
+    else if let AST::Expression(Expr::Boolean(value), _) = ast {
+      Object::Boolean(value)
+    }
+    else if let AST::Expression(Expr::Null, _) = ast {
+      Object::Null
+    }
+
+    else if let AST::Expression(Expr::Identifier(name), _) = ast {
+      if let Some(Object::Variable(var)) = self.env.get_var(name.clone()) {
+        *var.value.clone()
+      } else if let Some(func) = self.env.get_func(name.clone()) {
+        func.clone()
+      } else {
+        return Err(self.error(format!("Undefined variable [{}]", name)))
+      }
+    }
+
+    else if let AST::Expression(Expr::Lambda(decl), _) = ast {
+      Object::Function(Function{ decl, captured: self.env.vars.clone() })
+    }
 
-    else if let AST::Statement(Statement::Assignment(var)) = ast {
-      let variable = Object::Variable(Variable{ name: var.name.clone(), value: Box::new(self.handle_ast(*var.value)), ty: var.ty });
-      self.env.vars.insert(var.name, variable.clone());
+    else if let AST::Statement(Statement::Assignment(var), _) = ast {
+      let value = self.handle_ast(*var.value)?;
+      if ! self.type_matches(&value, &var.ty) {
+        return Err(self.error(format!("Cannot assign [{}] to [{}] of type [{}]", value, var.name, var.ty)))
+      }
+      let variable = Object::Variable(Variable{ name: var.name.clone(), value: Box::new(value), ty: var.ty });
+      self.env.set_var(var.name, variable.clone());
       variable
     }
 
+    else if let AST::Statement(Statement::Reassign{ target, op, value }, _) = ast {
+      let place = self.place(*target)?;
+      let value = match op {
+        Some(op) => {
+          let old = self.load(&place)?;
+          let value = self.handle_ast(*value)?;
+          match old.arithmetic(op, value) {
+            Ok(new) => new,
+            Err(message) => return Err(self.error(message))
+          }
+        },
+        None => self.handle_ast(*value)?
+      };
+      self.store(place, value.clone())?;
+      value
+    }
+
+    else if let AST::Expression(Expr::Increment{ target, op, prefix }, _) = ast {
+      let place = self.place(*target)?;
+      let old = self.load(&place)?;
+      let one = match &old {
+        Object::Decimal(_) => Object::Decimal(Decimal{ value: 1.0 }),
+        Object::Integer(_) | Object::BigInteger(_) => Object::Integer(Integer{ value: 1 }),
+        other => return Err(self.error(format!("Cannot apply [{}] to [{}]", if op == BinOp::Add { "++" } else { "--" }, other)))
+      };
+      let new = match old.clone().arithmetic(op, one) {
+        Ok(new) => new,
+        Err(message) => return Err(self.error(message))
+      };
+      self.store(place, new.clone())?;
+      if prefix { new } else { old }
+    }
+
+    else if let AST::Expression(Expr::Cast{ value, ty }, _) = ast {
+      let value = self.handle_ast(*value)?;
+      self.cast(value, &ty)?
+    }
+
+    else if let AST::Statement(Statement::Throw(value), _) = ast {
+      let value = self.handle_ast(*value)?;
+      return Err(self.throw(value));
+    }
+
+    else if let AST::Statement(Statement::Try(try_), _) = ast {
+      self.handle_try(try_)?
+    }
+
+    else if let AST::Statement(Statement::Enum(decl), _) = ast {
+      if self.env.get_type(decl.name.clone()).is_some() {
+        return Err(self.error(format!("Type [{}] is already defined", decl.name)))
+      }
+      self.env.set_type(decl.name.clone(), Object::Enum(decl.clone()));
+      Object::Enum(decl)
+    }
+
+    else if let AST::Expression(Expr::Variant{ enum_name, variant, args }, _) = ast {
+      let args: Vec<Object> = args.into_iter().map(|arg| self.handle_ast(arg)).collect::<Result<Vec<Object>, RuntimeError>>()?;
+      // math::sqrt(2.0)
+      match self.env.get_native(Some(&enum_name), &variant).cloned() {
+        Some(native) => self.call_native(native, args)?,
+        None => self.construct_variant(enum_name, variant, args)?
+      }
+    }
+
+    else if let AST::Expression(Expr::When(when), _) = ast {
+      self.handle_when(when)?
+    }
+
+    else if let AST::Statement(Statement::Function(decl), _) = ast {
+      let func = Object::Function(Function{ decl: decl.clone(), captured: HashMap::new() });
+      if self.env.set_func(decl.name.clone(), func.clone()).is_some() {
+        return Err(self.error(format!("Function [{}] is already defined", decl.name)))
+      }
+      func
+    }
+
+    else if let AST::Statement(Statement::Interface(decl), _) = ast {
+      if self.env.get_type(decl.name.clone()).is_some() {
+        return Err(self.error(format!("Type [{}] is already defined", decl.name)))
+      }
+      self.env.set_type(decl.name.clone(), Object::Interface(decl.clone()));
+      Object::Interface(decl)
+    }
+
+    else if let AST::Statement(Statement::Klass(decl), _) = ast {
+      if self.env.get_type(decl.name.clone()).is_some() {
+        return Err(self.error(format!("Type [{}] is already defined", decl.name)))
+      }
+      let mut env = Env::new();
+      for method in decl.methods {
+        env.set_func(method.name.clone(), Object::Function(Function{ decl: method, captured: HashMap::new() }));
+      }
+      let class = Object::Class(Class{ name: decl.name.clone(), interfaces: decl.interfaces, fields: decl.fields, env });
+      self.env.set_type(decl.name, class.clone());
+      class
+    }
+
+    else if let AST::Statement(Statement::Return(value), _) = ast {
+      let value = match value {
+        Some(value) => self.handle_ast(*value)?,
+        None => Object::Void
+      };
+      self.returning = Some(value.clone());
+      value
+    }
+
+    else if let AST::Expression(Expr::Call{ name, args }, _) = ast {
+      let args: Vec<Object> = args.into_iter().map(|arg| self.handle_ast(arg)).collect::<Result<Vec<Object>, RuntimeError>>()?;
+
+      if let Some(Object::Function(func)) = self.env.get_func(name.clone()) {
+        let func = func.clone();
+        self.call_function(func, args, None)?
+      } else if let Some(native) = self.env.get_native(None, &name) {
+        let native = native.clone();
+        self.call_native(native, args)?
+      } else if let Some(Object::Variable(Variable{ value, .. })) = self.env.get_var(name.clone()) {
+        match *value.clone() {
+          Object::Function(func) => self.call_function(func, args, None)?,
+          other => return Err(self.error(format!("[{}] is not a function, found [{}]", name, other)))
+        }
+      } else if let Some(Object::Class(class)) = self.env.get_type(name.clone()) {
+        let class = class.clone();
+        self.construct(class, args)?
+      } else {
+        return Err(self.error(format!("Undefined function [{}]", name)))
+      }
+    }
+
+    else if let AST::Expression(Expr::Field{ object, field, safe }, _) = ast {
+      match self.handle_ast(*object)? {
+        Object::Null if safe => Object::Null,
+        Object::Null => return Err(self.error(format!("Cannot read field [{}] of null", field))),
+        Object::Instance(mut instance) => match instance.env.get_var(field.clone()) {
+          Some(Object::Variable(var)) => *var.value.clone(),
+          _ => return Err(self.error(format!("Klass [{}] has no field [{}]", instance.class, field)))
+        },
+        other => return Err(self.error(format!("Cannot read field [{}] of [{}]", field, other)))
+      }
+    }
+
+    else if let AST::Expression(Expr::Index{ object, index }, _) = ast {
+      let items = self.handle_ast(*object)?;
+      let index = self.handle_ast(*index)?;
+      let at = self.index(&items, &index)?;
+      match items {
+        Object::Array(mut items) => items.swap_remove(at),
+        _ => unreachable!()
+      }
+    }
+
+    else if let AST::Expression(Expr::MethodCall{ object, method, args, safe }, _) = ast {
+      // Called on a variable or field, whatever the method does to `self` is stored back there.
+      let (place, mut this) = if object.place().is_some() {
+        let place = self.place(*object)?;
+        let this = self.load(&place)?;
+        (Some(place), this)
+      } else {
+        (None, self.handle_ast(*object)?)
+      };
+      if this == Object::Null {
+        if safe {
+          return Ok(Object::Null);
+        }
+        return Err(self.error(format!("Cannot call method [{}] on null", method)))
+      }
+      let args: Vec<Object> = args.into_iter().map(|arg| self.handle_ast(arg)).collect::<Result<Vec<Object>, RuntimeError>>()?;
+
+      // Dispatch on the runtime klass, whatever the static type of `object` was.
+      let class_name = match &this {
+        Object::Instance(instance) => instance.class.clone(),
+        Object::String(_) => match self.env.get_native(Some("String"), &method).cloned() {
+          Some(native) => return self.call_native(native, [vec!(this), args].concat()),
+          None => return Err(self.error(format!("String has no method [{}]", method)))
+        },
+        other => return Err(self.error(format!("Cannot call method [{}] on [{}]", method, other)))
+      };
+      let func = match self.env.get_type(class_name.clone()) {
+        Some(Object::Class(class)) => match class.env.get_func(method.clone()) {
+          Some(Object::Function(func)) => func.clone(),
+          _ => return Err(self.error(format!("Klass [{}] has no method [{}]", class_name, method)))
+        },
+        _ => return Err(self.error(format!("Undefined klass [{}]", class_name)))
+      };
+      let result = self.call_function(func, args, Some(&mut this))?;
+
+      if let Some(place) = place {
+        self.store(place, this)?;
+      }
+      result
+    }
+
+    else if let AST::Expression(Expr::Elvis{ left, right }, _) = ast {
+      match self.handle_ast(*left)? {
+        Object::Null => self.handle_ast(*right)?,
+        value => value
+      }
+    }
+
     else {
-      panic!("")
+      return Err(self.error(format!("Cannot run [{:?}]", ast)))
+    };
+    Ok(object)
+  }
+
+  // Position `index` stands for in the Array `items`.
+  fn index(&mut self, items: &Object, index: &Object) -> Result<usize, RuntimeError> {
+    let (items, index) = match (items, index) {
+      (Object::Array(items), Object::Integer(index)) => (items, index.value),
+      (Object::Null, _) => return Err(self.error("Cannot index null".to_string())),
+      (items, index) => return Err(self.error(format!("Cannot index [{}] with [{}]", items, index)))
+    };
+    match usize::try_from(index) {
+      Ok(at) if at < items.len() => Ok(at),
+      _ => Err(self.error(format!("Index [{}] is out of bounds for an Array of {} item{}", index, items.len(), if items.len() == 1 { "" } else { "s" })))
+    }
+  }
+
+  // The variable, field or element `target` names, with its indexes evaluated once.
+  fn place(&mut self, target: AST) -> Result<Place, RuntimeError> {
+    match target {
+      AST::Expression(Expr::Identifier(name), _) => Ok(Place::Variable(name)),
+      AST::Expression(Expr::Field{ object, field, .. }, _) => Ok(Place::Field(Box::new(self.place(*object)?), field)),
+      AST::Expression(Expr::Index{ object, index }, _) => {
+        let object = self.place(*object)?;
+        Ok(Place::Element(Box::new(object), Box::new(self.handle_ast(*index)?)))
+      },
+      target => Err(self.error(format!("Cannot assign to [{:?}]", target)))
+    }
+  }
+
+  fn load(&mut self, place: &Place) -> Result<Object, RuntimeError> {
+    match place {
+      Place::Variable(name) => match self.env.get_var(name.clone()) {
+        Some(Object::Variable(var)) => Ok(*var.value.clone()),
+        _ => Err(self.error(format!("Undefined variable [{}]", name)))
+      },
+      Place::Field(object, field) => match self.load(object)? {
+        Object::Null => Err(self.error(format!("Cannot read field [{}] of null", field))),
+        Object::Instance(mut instance) => match instance.env.get_var(field.clone()) {
+          Some(Object::Variable(var)) => Ok(*var.value.clone()),
+          _ => Err(self.error(format!("Klass [{}] has no field [{}]", instance.class, field)))
+        },
+        other => Err(self.error(format!("Cannot read field [{}] of [{}]", field, other)))
+      },
+      Place::Element(object, index) => {
+        let items = self.load(object)?;
+        let at = self.index(&items, index)?;
+        match items {
+          Object::Array(mut items) => Ok(items.swap_remove(at)),
+          _ => unreachable!()
+        }
+      }
+    }
+  }
+
+  // Stores `value` in the place, which keeps its declared type.
+  fn store(&mut self, place: Place, value: Object) -> Result<(), RuntimeError> {
+    match place {
+      Place::Variable(name) => {
+        let ty = match self.env.get_var(name.clone()) {
+          Some(Object::Variable(var)) => var.ty.clone(),
+          _ => return Err(self.error(format!("Undefined variable [{}]", name)))
+        };
+        if ! self.type_matches(&value, &ty) {
+          return Err(self.error(format!("Cannot assign [{}] to [{}] of type [{}]", value, name, ty)))
+        }
+        self.env.set_var(name.clone(), Object::Variable(Variable{ name, ty, value: Box::new(value) }));
+      },
+      Place::Field(object, field) => {
+        let mut instance = match self.load(&object)? {
+          Object::Instance(instance) => instance,
+          Object::Null => return Err(self.error(format!("Cannot set field [{}] of null", field))),
+          other => return Err(self.error(format!("Cannot set field [{}] of [{}]", field, other)))
+        };
+        let ty = match instance.env.get_var(field.clone()) {
+          Some(Object::Variable(var)) => var.ty.clone(),
+          _ => return Err(self.error(format!("Klass [{}] has no field [{}]", instance.class, field)))
+        };
+        if ! self.type_matches(&value, &ty) {
+          return Err(self.error(format!("Field [{}.{}] is of type [{}], instead found [{}]", instance.class, field, ty, value)))
+        }
+        instance.env.set_var(field.clone(), Object::Variable(Variable{ name: field, ty, value: Box::new(value) }));
+        // Instances are values, the changed copy goes back where it came from.
+        self.store(*object, Object::Instance(instance))?;
+      },
+      Place::Element(object, index) => {
+        let items = self.load(&object)?;
+        let at = self.index(&items, &index)?;
+        let mut items = match items {
+          Object::Array(items) => items,
+          _ => unreachable!()
+        };
+        items[at] = value;
+        // Arrays are values too.
+        self.store(*object, Object::Array(items))?;
+      }
+    }
+    Ok(())
+  }
+
+  // Numbers convert, Decimals toward zero. Anything else has to already be of the type.
+  fn cast(&mut self, value: Object, ty: &Type) -> Result<Object, RuntimeError> {
+    let object = match (value, ty.non_null()) {
+      (Object::Null, _) if ty.is_nullable() => Object::Null,
+      (Object::Null, _) => return Err(self.error(format!("Cannot cast null to [{}]", ty))),
+      (Object::Integer(int), Type::Decimal) => Object::Decimal(Decimal{ value: int.value as f64 }),
+      (Object::Integer(int), Type::BigInteger) => Object::BigInteger(BigInteger{ value: BigInt::from_i64(int.value) }),
+      (Object::Decimal(dec), Type::Integer) => {
+        // i64::MAX as f64 rounds up to 2^63, which is already out of range.
+        if ! dec.value.is_finite() || dec.value.trunc() >= i64::MAX as f64 || dec.value.trunc() < i64::MIN as f64 {
+          return Err(self.error(format!("[{}] does not fit in an Integer", dec.value)))
+        }
+        Object::Integer(Integer{ value: dec.value.trunc() as i64 })
+      },
+      (Object::Decimal(dec), Type::BigInteger) => match BigInt::parse(&format!("{:.0}", dec.value.trunc())) {
+        Some(value) if dec.value.is_finite() => Object::BigInteger(BigInteger{ value }),
+        _ => return Err(self.error(format!("[{}] cannot be a BigInteger", dec.value)))
+      },
+      (Object::BigInteger(big), Type::Integer) => match big.value.to_i64() {
+        Some(value) => Object::Integer(Integer{ value }),
+        None => return Err(self.error(format!("[{}] does not fit in an Integer", big.value)))
+      },
+      (Object::BigInteger(big), Type::Decimal) => Object::Decimal(Decimal{ value: big.value.to_string().parse::<f64>().unwrap() }),
+      (value, _) if self.type_matches(&value, ty) => value,
+      (value, _) => return Err(self.error(format!("Cannot cast [{}] to [{}]", value, ty)))
+    };
+    Ok(object)
+  }
+
+  fn type_matches(&mut self, value: &Object, ty: &Type) -> bool {
+    match (ty, value) {
+      (Type::Any, _) | (Type::Param(_), _) => true,
+      (Type::Integer, Object::Integer(_)) => true,
+      (Type::Decimal, Object::Decimal(_)) => true,
+      (Type::BigInteger, Object::BigInteger(_)) => true,
+      (Type::String, Object::String(_)) => true,
+      (Type::Boolean, Object::Boolean(_)) => true,
+      (Type::Array(item), Object::Array(items)) => items.iter().all(|value| self.type_matches(value, item)),
+      (Type::Hash(key, value), Object::Hash(entries)) => entries.iter().all(|(k, v)| self.type_matches(k, key) && self.type_matches(v, value)),
+      (Type::Void, Object::Void) => true,
+      (Type::Nullable(_), Object::Null) => true,
+      (Type::Nullable(inner), value) => self.type_matches(value, inner),
+      (Type::Function(_, _), Object::Function(func)) => &func.decl.ty() == ty,
+      // The values of a generic enum are checked against its type arguments, an Option<Integer> only holds Integers.
+      (Type::Named(name, args), Object::EnumValue(val)) if &val.enum_name == name => {
+        let decl = match self.env.get_type(name.clone()) {
+          Some(Object::Enum(decl)) => decl.clone(),
+          _ => return false
+        };
+        let bound = decl.params.iter().cloned().zip(args.iter().cloned()).collect();
+        match decl.variant(&val.variant) {
+          Some(variant) => variant.fields.iter().zip(&val.values).all(|(field, value)| self.type_matches(value, &field.substitute(&bound))),
+          None => false
+        }
+      },
+      (Type::Named(name, _), Object::Instance(instance)) => {
+        if &instance.class == name {
+          return true;
+        }
+        match self.env.get_type(instance.class.clone()) {
+          Some(Object::Class(class)) => class.interfaces.contains(name),
+          _ => false
+        }
+      },
+      _ => false
+    }
+  }
+
+  // Runs statements until the end of the block or a `return`.
+  fn run_block(&mut self, body: Vec<AST>) -> Result<Option<Object>, RuntimeError> {
+    let mut result = None;
+    for ast in body {
+      result = Some(self.handle_ast(ast)?);
+      if self.returning.is_some() {
+        break;
+      }
+    }
+    Ok(result)
+  }
+
+  // A method gets `this` as `self`, and whatever `self` holds at the end is left in `this`.
+  // Runs `body` with `bindings` as variables, they only live for the block.
+  fn run_block_with(&mut self, bindings: Vec<Variable>, body: Vec<AST>) -> Result<Option<Object>, RuntimeError> {
+    let mut shadowed = vec!();
+    for variable in bindings {
+      shadowed.push((variable.name.clone(), self.env.set_var(variable.name.clone(), Object::Variable(variable))));
+    }
+
+    let result = self.run_block(body);
+
+    // Put back whatever the bindings shadowed.
+    for (name, old) in shadowed {
+      match old {
+        Some(old) => { self.env.set_var(name, old); },
+        None => { self.env.remove_var(name); }
+      }
+    }
+    result
+  }
+
+  fn handle_try(&mut self, try_: Try) -> Result<Object, RuntimeError> {
+    let mut result = self.run_block(try_.body);
+
+    if let Err(error) = &result {
+      let value = self.caught(error);
+      for catch in try_.catches {
+        if self.type_matches(&value, &catch.ty) {
+          let binding = Variable{ name: catch.name, ty: catch.ty, value: Box::new(value) };
+          result = self.run_block_with(vec!(binding), catch.body);
+          break;
+        }
+      }
+    }
+
+    if let Some(finally) = try_.finally {
+      let returning = self.returning.take();
+      self.run_block(finally)?;
+      // A `return` in finally wins over whatever happened before, errors included.
+      if self.returning.is_some() {
+        return Ok(Object::Void);
+      }
+      self.returning = returning;
     }
+    Ok(result?.unwrap_or(Object::Void))
   }
 
-}
\ No newline at end of file
+  fn call_function(&mut self, func: Function, args: Vec<Object>, this: Option<&mut Object>) -> Result<Object, RuntimeError> {
+    let decl = func.decl;
+    if decl.params.len() != args.len() {
+      return Err(self.error(format!("[{}] takes {} arguments but {} were given", decl.name, decl.params.len(), args.len())))
+    }
+
+    // Functions only see their parameters, `self`, their own locals and whatever a lambda captured.
+    let mut locals = func.captured;
+    for (param, arg) in decl.params.iter().zip(args) {
+      let (name, ty) = param.argument();
+      if ! self.type_matches(&arg, &ty) {
+        return Err(self.error(format!("[{}] expected [{}] to be of type [{}], instead found [{}]", decl.name, name, ty, arg)))
+      }
+      locals.insert(name.clone(), Object::Variable(Variable{ name, ty, value: Box::new(arg) }));
+    }
+    if let Some(this) = &this {
+      let ty = match this {
+        Object::Instance(instance) => Type::Named(instance.class.clone(), vec!()),
+        _ => Type::Any
+      };
+      locals.insert("self".to_string(), Object::Variable(Variable{ name: "self".to_string(), ty, value: Box::new((*this).clone()) }));
+    }
+
+    if self.frames.len() >= MAX_CALL_DEPTH {
+      return Err(self.error(format!("Calls nested more than {} deep, [{}] may recurse forever", MAX_CALL_DEPTH, decl.name)))
+    }
+    let globals = std::mem::replace(&mut self.env.vars, locals);
+    self.frames.push(Frame{ function: decl.name.clone(), call: self.span.clone() });
+    let last = self.run_block(decl.body);
+    self.frames.pop();
+    let locals = std::mem::replace(&mut self.env.vars, globals);
+    let last = last?;
+
+    if let (Some(this), Some(Object::Variable(var))) = (this, locals.get("self")) {
+      *this = *var.value.clone();
+    }
+
+    let returned = self.returning.take();
+    if decl.return_type == Type::Void {
+      return Ok(Object::Void);
+    }
+
+    let result = match returned.or(last) {
+      Some(result) => result,
+      None => return Err(self.error(format!("[{}] did not produce a value", decl.name)))
+    };
+    if ! self.type_matches(&result, &decl.return_type) {
+      return Err(self.error(format!("[{}] should return [{}], instead returned [{}]", decl.name, decl.return_type, result)))
+    }
+    Ok(result)
+  }
+
+  fn call_native(&mut self, native: Native, args: Vec<Object>) -> Result<Object, RuntimeError> {
+    if ! native.overloads.is_empty() {
+      for candidate in native.candidates() {
+        let count_matches = if candidate.variadic { args.len() >= candidate.params.len() } else { args.len() == candidate.params.len() };
+        if count_matches && candidate.params.iter().zip(&args).all(|(param, arg)| self.type_matches(arg, param)) {
+          return (candidate.func)(&args).map_err(|message| self.error(message));
+        }
+      }
+      let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+      return Err(self.error(format!("No [{}] takes [{}]", native.name, args.join(", "))))
+    }
+    let count_matches = if native.variadic { args.len() >= native.params.len() } else { args.len() == native.params.len() };
+    if ! count_matches {
+      return Err(self.error(format!("[{}] takes {} arguments but {} were given", native.name, native.params.len(), args.len())))
+    }
+    for (i, (param, arg)) in native.params.iter().zip(&args).enumerate() {
+      if ! self.type_matches(arg, param) {
+        return Err(self.error(format!("Argument {} of [{}] should be [{}], instead found [{}]", i + 1, native.name, param, arg)))
+      }
+    }
+    (native.func)(&args).map_err(|message| self.error(message))
+  }
+
+  fn construct(&mut self, class: Class, args: Vec<Object>) -> Result<Object, RuntimeError> {
+    if class.fields.len() != args.len() {
+      return Err(self.error(format!("[{}] has {} fields but {} values were given", class.name, class.fields.len(), args.len())))
+    }
+
+    let mut env = Env::new();
+    for (field, arg) in class.fields.iter().zip(args) {
+      let (name, ty) = field.argument();
+      if ! self.type_matches(&arg, &ty) {
+        return Err(self.error(format!("Field [{}.{}] is of type [{}], instead found [{}]", class.name, name, ty, arg)))
+      }
+      env.set_var(name.clone(), Object::Variable(Variable{ name, ty, value: Box::new(arg) }));
+    }
+    let fields = class.fields.iter().map(|field| field.argument().0).collect();
+    Ok(Object::Instance(Instance{ class: class.name, fields, env }))
+  }
+
+  fn construct_variant(&mut self, enum_name: String, variant: String, values: Vec<Object>) -> Result<Object, RuntimeError> {
+    let decl = self.get_enum(&enum_name)?;
+    let fields = match decl.variant(&variant) {
+      Some(v) => v.fields.clone(),
+      None => return Err(self.error(format!("Enum [{}] has no variant [{}]", enum_name, variant)))
+    };
+
+    if fields.len() != values.len() {
+      return Err(self.error(format!("[{}::{}] takes {} values but {} were given", enum_name, variant, fields.len(), values.len())))
+    }
+
+    for (value, ty) in values.iter().zip(fields.iter()) {
+      if ! self.type_matches(value, ty) {
+        return Err(self.error(format!("[{}::{}] expected a value of type [{}], instead found [{}]", enum_name, variant, ty, value)))
+      }
+    }
+    Ok(Object::EnumValue(EnumValue{ enum_name, variant, values }))
+  }
+
+  fn get_enum(&mut self, name: &str) -> Result<EnumDecl, RuntimeError> {
+    match self.env.get_type(name.to_string()) {
+      Some(Object::Enum(decl)) => Ok(decl.clone()),
+      _ => Err(self.error(format!("Undefined enum [{}]", name)))
+    }
+  }
+
+  fn handle_when(&mut self, when: When) -> Result<Object, RuntimeError> {
+    let arms = when.arms;
+    let subject = match self.handle_ast(*when.subject)? {
+      Object::EnumValue(val) => val,
+      other => return Err(self.error(format!("Can only use `when` on enum values, instead found [{}]", other)))
+    };
+    let decl = self.get_enum(&subject.enum_name)?;
+
+    for arm in arms {
+      let bindings = match arm.pattern {
+        Pattern::Wildcard => vec!(),
+        Pattern::Variant{ enum_name, variant, bindings } => {
+          if enum_name != subject.enum_name {
+            return Err(self.error(format!("Cannot match [{}::{}] against a value of enum [{}]", enum_name, variant, subject.enum_name)))
+          }
+          if variant != subject.variant {
+            continue;
+          }
+          if bindings.len() != subject.values.len() {
+            return Err(self.error(format!("[{}::{}] holds {} values but the pattern binds {}", enum_name, variant, subject.values.len(), bindings.len())))
+          }
+          bindings
+        }
+      };
+
+      let fields = decl.variant(&subject.variant).unwrap().fields.clone();
+      let bindings = bindings.into_iter().zip(subject.values.clone()).zip(fields).map(|((name, value), ty)| Variable{ name, ty, value: Box::new(value) }).collect();
+      return match self.run_block_with(bindings, arm.body)? {
+        Some(result) => Ok(result),
+        None => Err(self.error("`when` arm has an empty body".to_string()))
+      };
+    }
+    Err(self.error(format!("No arm of `when` matched [{}::{}]", subject.enum_name, subject.variant)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use checker::Checker;
+  use cst::SourceFile;
+  use natives::{self, Options};
+  use super::*;
+
+  // What running `code` as main.fk reports.
+  fn error(code: &str) -> String {
+    let mut files = Files::new();
+    let file = files.add("main.fk", code);
+    let mut program = SourceFile::parse(code, file).unwrap().to_ast(file).unwrap();
+    let natives = natives::registry(&Options{ io: false, args: vec!(), seed: Some(0) });
+    Checker::new(natives.clone()).check(&mut program).unwrap();
+    let mut interpreter = Interpreter::new(program, files, natives);
+    interpreter.interpret(vec!()).unwrap_err().render(&interpreter.files)
+  }
+
+  #[test]
+  fn errors_point_at_the_node_that_failed() {
+    assert_eq!(error("Integer a = 1\nInteger b = (a + 2) / 0\n"),
+      "error: Division by zero\n --> main.fk:2:13\n  |\n2 | Integer b = (a + 2) / 0\n  |             ^^^^^^^^^^^\n  = at <top> (main.fk:2:13)");
+  }
+
+  #[test]
+  fn traces_list_the_calls_innermost_first() {
+    let code = "funk inner(Integer n) Integer {\n  return n / 0\n}\nfunk outer() Integer {\n  return inner(1)\n}\nouter()\n";
+    assert_eq!(error(code), "error: Division by zero\n --> main.fk:2:10\n  |\n2 |   return n / 0\n  |          ^^^^^\n\
+      \x20 = at inner (main.fk:2:10)\n  = at outer (main.fk:5:10)\n  = at <top> (main.fk:7:1)");
+  }
+
+  #[test]
+  fn compound_assignments_fail_at_the_statement() {
+    let rendered = error("mut Integer big = 9223372036854775807\nbig += 1\n");
+    assert!(rendered.contains(" --> main.fk:2:1\n  |\n2 | big += 1\n  | ^^^^^^^^"), "{}", rendered);
+  }
+}
@@ -0,0 +1,360 @@
+use std::str::Chars;
+use std::iter::Peekable;
+use bigint::BigInt;
+use structs::structs::*;
+
+// Deeper documents are refused rather than overflowing the stack.
+const MAX_DEPTH: usize = 512;
+
+// Objects become Hashes with String keys in document order. Numbers written without a fraction or
+// exponent become an Integer, or a BigInteger when they do not fit, the others a Decimal.
+pub fn parse(text: &str) -> Result<Object, String> {
+  let mut parser = JsonParser{ chars: text.chars().peekable(), line: 1, column: 1, depth: 0 };
+  parser.skip_whitespace();
+  let value = parser.value()?;
+  parser.skip_whitespace();
+  match parser.chars.peek().cloned() {
+    None => Ok(value),
+    Some(c) => Err(parser.error(format!("expected the end of the document, found [{}]", c)))
+  }
+}
+
+struct JsonParser<'a> {
+  chars: Peekable<Chars<'a>>,
+  // Of the next char, both from 1.
+  line: usize,
+  column: usize,
+  depth: usize
+}
+
+impl<'a> JsonParser<'a> {
+  fn error(&self, message: String) -> String {
+    format!("Invalid JSON at {}:{}: {}", self.line, self.column, message)
+  }
+
+  fn bump(&mut self) -> Option<char> {
+    let c = self.chars.next()?;
+    if c == '\n' {
+      self.line += 1;
+      self.column = 1;
+    } else {
+      self.column += 1;
+    }
+    Some(c)
+  }
+
+  fn expect(&mut self, expected: char) -> Result<(), String> {
+    match self.chars.peek().cloned() {
+      Some(c) if c == expected => { self.bump(); Ok(()) },
+      Some(c) => Err(self.error(format!("expected [{}], found [{}]", expected, c))),
+      None => Err(self.error(format!("expected [{}], found the end of the document", expected)))
+    }
+  }
+
+  fn skip_whitespace(&mut self) {
+    while let Some(' ' | '\t' | '\n' | '\r') = self.chars.peek() {
+      self.bump();
+    }
+  }
+
+  fn value(&mut self) -> Result<Object, String> {
+    match self.chars.peek().cloned() {
+      Some('{') => self.nested(JsonParser::object),
+      Some('[') => self.nested(JsonParser::array),
+      Some('"') => Ok(Object::String(Str{ value: self.string()? })),
+      Some('-' | '0'..='9') => self.number(),
+      Some('t') => self.word("true", Object::Boolean(true)),
+      Some('f') => self.word("false", Object::Boolean(false)),
+      Some('n') => self.word("null", Object::Null),
+      Some(c) => Err(self.error(format!("expected a value, found [{}]", c))),
+      None => Err(self.error("expected a value, found the end of the document".to_string()))
+    }
+  }
+
+  fn word(&mut self, word: &str, value: Object) -> Result<Object, String> {
+    for expected in word.chars() {
+      if self.chars.peek() != Some(&expected) {
+        return Err(self.error(format!("expected [{}]", word)));
+      }
+      self.bump();
+    }
+    Ok(value)
+  }
+
+  fn nested(&mut self, parse: fn(&mut JsonParser<'a>) -> Result<Object, String>) -> Result<Object, String> {
+    if self.depth == MAX_DEPTH {
+      return Err(self.error(format!("nested deeper than {} levels", MAX_DEPTH)));
+    }
+    self.depth += 1;
+    let value = parse(self);
+    self.depth -= 1;
+    value
+  }
+
+  fn object(&mut self) -> Result<Object, String> {
+    self.expect('{')?;
+    let mut entries: Vec<(Object, Object)> = vec!();
+    self.skip_whitespace();
+    if self.chars.peek() == Some(&'}') {
+      self.bump();
+      return Ok(Object::Hash(entries));
+    }
+    loop {
+      self.skip_whitespace();
+      if self.chars.peek() != Some(&'"') {
+        return Err(self.error("expected a key in double quotes".to_string()));
+      }
+      let key = Object::String(Str{ value: self.string()? });
+      self.skip_whitespace();
+      self.expect(':')?;
+      self.skip_whitespace();
+      let value = self.value()?;
+      // A repeated key keeps its first place and its last value.
+      match entries.iter_mut().find(|(k, _)| *k == key) {
+        Some(entry) => entry.1 = value,
+        None => entries.push((key, value))
+      }
+      self.skip_whitespace();
+      match self.chars.peek() {
+        Some(',') => { self.bump(); },
+        Some('}') => { self.bump(); return Ok(Object::Hash(entries)); },
+        _ => return Err(self.error("expected [,] or [}] after a value in an object".to_string()))
+      }
+    }
+  }
+
+  fn array(&mut self) -> Result<Object, String> {
+    self.expect('[')?;
+    let mut items = vec!();
+    self.skip_whitespace();
+    if self.chars.peek() == Some(&']') {
+      self.bump();
+      return Ok(Object::Array(items));
+    }
+    loop {
+      self.skip_whitespace();
+      items.push(self.value()?);
+      self.skip_whitespace();
+      match self.chars.peek() {
+        Some(',') => { self.bump(); },
+        Some(']') => { self.bump(); return Ok(Object::Array(items)); },
+        _ => return Err(self.error("expected [,] or []] after a value in an array".to_string()))
+      }
+    }
+  }
+
+  fn string(&mut self) -> Result<String, String> {
+    self.expect('"')?;
+    let mut value = String::new();
+    loop {
+      match self.bump() {
+        Some('"') => return Ok(value),
+        Some('\\') => match self.bump() {
+          Some('"') => value.push('"'),
+          Some('\\') => value.push('\\'),
+          Some('/') => value.push('/'),
+          Some('b') => value.push('\u{8}'),
+          Some('f') => value.push('\u{c}'),
+          Some('n') => value.push('\n'),
+          Some('r') => value.push('\r'),
+          Some('t') => value.push('\t'),
+          Some('u') => value.push(self.unicode()?),
+          _ => return Err(self.error("unknown escape in a string".to_string()))
+        },
+        Some(c) if (c as u32) < 0x20 => return Err(self.error("control characters in a string must be escaped".to_string())),
+        Some(c) => value.push(c),
+        None => return Err(self.error("unterminated string".to_string()))
+      }
+    }
+  }
+
+  // After `\u`, joining a surrogate pair when one follows.
+  fn unicode(&mut self) -> Result<char, String> {
+    let high = self.hex()?;
+    if ! (0xD800..0xDC00).contains(&high) {
+      return char::from_u32(high).ok_or_else(|| self.error("invalid \\u escape".to_string()));
+    }
+    if self.bump() != Some('\\') || self.bump() != Some('u') {
+      return Err(self.error("expected the second half of a surrogate pair".to_string()));
+    }
+    let low = self.hex()?;
+    if ! (0xDC00..0xE000).contains(&low) {
+      return Err(self.error("expected the second half of a surrogate pair".to_string()));
+    }
+    char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)).ok_or_else(|| self.error("invalid \\u escape".to_string()))
+  }
+
+  fn hex(&mut self) -> Result<u32, String> {
+    let mut code = 0;
+    for _ in 0..4 {
+      match self.bump().and_then(|c| c.to_digit(16)) {
+        Some(digit) => code = code * 16 + digit,
+        None => return Err(self.error("expected 4 hex digits after \\u".to_string()))
+      }
+    }
+    Ok(code)
+  }
+
+  fn number(&mut self) -> Result<Object, String> {
+    let mut text = String::new();
+    let mut whole = true;
+    if self.chars.peek() == Some(&'-') {
+      text.push('-');
+      self.bump();
+    }
+    match self.chars.peek() {
+      Some('0') => { text.push('0'); self.bump(); },
+      Some('1'..='9') => self.digits(&mut text),
+      _ => return Err(self.error("expected a digit".to_string()))
+    }
+    if self.chars.peek() == Some(&'.') {
+      whole = false;
+      text.push('.');
+      self.bump();
+      if ! matches!(self.chars.peek(), Some('0'..='9')) {
+        return Err(self.error("expected a digit after [.]".to_string()));
+      }
+      self.digits(&mut text);
+    }
+    if let Some('e' | 'E') = self.chars.peek() {
+      whole = false;
+      text.push('e');
+      self.bump();
+      if let Some(sign @ ('+' | '-')) = self.chars.peek().cloned() {
+        text.push(sign);
+        self.bump();
+      }
+      if ! matches!(self.chars.peek(), Some('0'..='9')) {
+        return Err(self.error("expected a digit in the exponent".to_string()));
+      }
+      self.digits(&mut text);
+    }
+
+    if whole {
+      return Ok(match text.parse::<i64>() {
+        Ok(value) => Object::Integer(Integer{ value }),
+        Err(_) => Object::BigInteger(BigInteger{ value: BigInt::parse(&text).unwrap() })
+      });
+    }
+    match text.parse::<f64>() {
+      Ok(value) if value.is_finite() => Ok(Object::Decimal(Decimal{ value })),
+      _ => Err(self.error(format!("[{}] is too large", text)))
+    }
+  }
+
+  fn digits(&mut self, text: &mut String) {
+    while let Some(c @ '0'..='9') = self.chars.peek().cloned() {
+      text.push(c);
+      self.bump();
+    }
+  }
+}
+
+// Compact when `indent` is None, otherwise one member per line indented by that many spaces.
+pub fn stringify(value: &Object, indent: Option<usize>) -> Result<String, String> {
+  let mut out = String::new();
+  write(value, indent, 0, &mut out)?;
+  Ok(out)
+}
+
+fn write(value: &Object, indent: Option<usize>, depth: usize, out: &mut String) -> Result<(), String> {
+  match value {
+    Object::Null => out.push_str("null"),
+    Object::Boolean(b) => out.push_str(&b.to_string()),
+    Object::Integer(i) => out.push_str(&i.value.to_string()),
+    Object::BigInteger(b) => out.push_str(&b.value.to_string()),
+    Object::Decimal(d) if d.value.is_finite() => out.push_str(&format!("{:?}", d.value)),
+    Object::Decimal(d) => return Err(format!("JSON has no [{:?}]", d.value)),
+    Object::String(s) => quote(&s.value, out),
+    Object::Variable(var) => write(&var.value, indent, depth, out)?,
+    Object::Array(items) => {
+      let items: Vec<(Option<&str>, &Object)> = items.iter().map(|item| (None, item)).collect();
+      write_members('[', ']', &items, indent, depth, out)?;
+    },
+    Object::Hash(entries) => {
+      let mut members = vec!();
+      for (key, value) in entries {
+        match key {
+          Object::String(key) => members.push((Some(key.value.as_str()), value)),
+          other => return Err(format!("JSON keys must be Strings, found [{}]", other))
+        }
+      }
+      write_members('{', '}', &members, indent, depth, out)?;
+    },
+    // Klass instances become objects of their fields.
+    Object::Instance(instance) => {
+      let mut members = vec!();
+      for name in &instance.fields {
+        if let Some(value) = instance.env.vars.get(name) {
+          members.push((Some(name.as_str()), value));
+        }
+      }
+      write_members('{', '}', &members, indent, depth, out)?;
+    },
+    other => return Err(format!("Cannot convert [{}] to JSON", other))
+  }
+  Ok(())
+}
+
+fn write_members(open: char, close: char, members: &[(Option<&str>, &Object)], indent: Option<usize>, depth: usize, out: &mut String) -> Result<(), String> {
+  out.push(open);
+  for (i, (key, value)) in members.iter().enumerate() {
+    if i > 0 {
+      out.push(',');
+    }
+    if let Some(indent) = indent {
+      out.push('\n');
+      out.push_str(&" ".repeat(indent * (depth + 1)));
+    }
+    if let Some(key) = key {
+      quote(key, out);
+      out.push_str(if indent.is_some() { ": " } else { ":" });
+    }
+    write(value, indent, depth + 1, out)?;
+  }
+  if let (Some(indent), false) = (indent, members.is_empty()) {
+    out.push('\n');
+    out.push_str(&" ".repeat(indent * depth));
+  }
+  out.push(close);
+  Ok(())
+}
+
+fn quote(value: &str, out: &mut String) {
+  out.push('"');
+  for c in value.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+      c => out.push(c)
+    }
+  }
+  out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trips_documents() {
+    let text = r#"{"name": "Ada\né😀", "tags": [1, -2.5, 1e3, true, null, {}], "big": 12345678901234567890}"#;
+    let value = parse(text).unwrap();
+    assert_eq!(stringify(&value, None).unwrap(), "{\"name\":\"Ada\\né😀\",\"tags\":[1,-2.5,1000.0,true,null,{}],\"big\":12345678901234567890}");
+    assert_eq!(stringify(&parse("[1, {\"a\": []}]").unwrap(), Some(2)).unwrap(), "[\n  1,\n  {\n    \"a\": []\n  }\n]");
+    assert_eq!(parse(&stringify(&value, Some(4)).unwrap()).unwrap(), value);
+    assert_eq!(parse("-99999999999999999999"), Ok(Object::BigInteger(BigInteger{ value: BigInt::parse("-99999999999999999999").unwrap() })));
+  }
+
+  #[test]
+  fn errors_point_at_line_and_column() {
+    assert_eq!(parse("{\n  \"a\": 1,\n  \"b\" 2\n}"), Err("Invalid JSON at 3:7: expected [:], found [2]".to_string()));
+    assert_eq!(parse("[1, 2"), Err("Invalid JSON at 1:6: expected [,] or []] after a value in an array".to_string()));
+    assert_eq!(parse("01"), Err("Invalid JSON at 1:2: expected the end of the document, found [1]".to_string()));
+    assert!(parse(&"[".repeat(MAX_DEPTH + 1)).unwrap_err().contains("nested deeper than 512 levels"));
+  }
+}
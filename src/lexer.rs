@@ -1,158 +1,407 @@
-use std::slice::Iter;
-use std::iter::Peekable;
+use std::fmt;
 use std::ops::Range;
+use bigint::BigInt;
+use interner::Symbol;
 use structs::structs::*;
 
-pub struct Lexer {
-  pub code: String,
-  pub pos: usize,
-  pub last_pos: usize,
-  pub line_pos: usize,
+// Hands out tokens one at a time straight from the source, nothing is buffered.
+pub struct Lexer<'a> {
+  code: &'a str,
+  // Byte offset of the next char.
+  pos: usize,
+  line: usize,
+  column: usize,
+  utf16_column: usize,
+  // Where the token being lexed started.
+  start: usize,
+  start_line: usize,
+  start_column: usize,
+  start_utf16_column: usize,
+  failed: bool
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+  pub message: String,
+  // Zero based, like the token positions.
   pub line: usize,
-  pub current_char: Option<char>,
-  pub next_char: Option<char>
+  pub column: usize,
+  // Bytes of the source, from the start of the token to where it went wrong.
+  pub range: Range<usize>
 }
 
-impl Lexer {
-  pub fn lex(&mut self) -> Vec<Token> {
-    let chars = self.code.chars().collect::<Vec<char>>();
-
-    self.last_pos = chars.len() - 1;
-    let mut iter = chars.iter().peekable();
-
-    self.parse_tokens(&mut iter)
-  }
-
-  fn parse_tokens(&mut self, iter: &mut Peekable<Iter<char>>) -> Vec<Token> {
-    let mut tokens: Vec<Token> = Vec::new();
-    let mut current = iter.next();
-
-    while self.pos <= self.last_pos {
-      tokens.push(match current {
-        None => {break},
-        Some(cur) => {
-          match cur {
-            c if ['(', ')', '}', '{', '[', ']', ',', ';'].contains(c) => { 
-              Token{ ty: TokenType::new(c.to_string()), line: self.line, range: Range{ start: self.pos, end: self.pos + 1} }
-            },
-            space if space.is_whitespace() => {
-              if *space == '\n' {
-                self.line += 1;
-                self.line_pos = 0;
-                Token{ ty: TokenType::Newline, line: self.line, range: Range{ start: self.pos, end: self.pos + 1} }
-              } else {
-                current = iter.next();
-                self.pos += 1;
-                self.line_pos += 1;
-                continue;
-              }
-            },
-            num if num.is_numeric() => self.lex_numbers(iter, *num),
-            op if OPEARTORS.contains(&&*op.to_string()) => self.lex_operators(iter, *op),
-            string if string == &'\"' => self.lex_string(iter),
-            keyword if keyword.is_alphabetic() || keyword == &'_' => self.lex_keywords(iter, *keyword),
-            _ => Token{ ty: TokenType::Unknown, line: self.line, range: Range{ start: self.pos, end: self.pos + 1} },
-          }
-        }
-      });
-      current = iter.next();
-      self.pos += 1;
-      self.line_pos += 1;
+impl fmt::Display for LexError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} on line {}, column {}", self.message, self.line + 1, self.column + 1)
+  }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+  type Item = Result<Token, LexError>;
+
+  // Stops after the first error.
+  fn next(&mut self) -> Option<Result<Token, LexError>> {
+    if self.failed {
+      return None;
+    }
+    while self.peek().is_some_and(|c| c.is_whitespace() && c != '\n') {
+      self.bump();
+    }
+
+    self.start = self.pos;
+    self.start_line = self.line;
+    self.start_column = self.column;
+    self.start_utf16_column = self.utf16_column;
+
+    let token = match self.bump()? {
+      c if ['(', ')', '}', '{', '[', ']', ',', ';'].contains(&c) => Ok(self.token(TokenType::new(c.to_string()), TokenValue::None)),
+      '\n' => Ok(self.token(TokenType::Newline, TokenValue::None)),
+      num if num.is_ascii_digit() => self.lex_numbers(num),
+      '/' if self.peek() == Some('/') || self.peek() == Some('*') => self.lex_comment(),
+      op if OPEARTORS.contains(&&*op.to_string()) => Ok(self.lex_operators(op)),
+      '\"' => self.lex_string(),
+      keyword if keyword.is_alphabetic() || keyword == '_' => Ok(self.lex_keywords(keyword)),
+      _ => Ok(self.token(TokenType::Unknown, TokenValue::None))
+    };
+
+    self.failed = token.is_err();
+    Some(token)
+  }
+}
+
+impl<'a> Lexer<'a> {
+  pub fn new(code: &'a str) -> Lexer<'a> {
+    Lexer {
+      code,
+      pos: 0,
+      line: 0,
+      column: 0,
+      utf16_column: 0,
+      start: 0,
+      start_line: 0,
+      start_column: 0,
+      start_utf16_column: 0,
+      failed: false
+    }
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.code[self.pos..].chars().next()
+  }
+
+  fn peek_second(&self) -> Option<char> {
+    self.code[self.pos..].chars().nth(1)
+  }
+
+  fn bump(&mut self) -> Option<char> {
+    let c = self.peek()?;
+    self.pos += c.len_utf8();
+    if c == '\n' {
+      self.line += 1;
+      self.column = 0;
+      self.utf16_column = 0;
+    } else {
+      self.column += 1;
+      self.utf16_column += c.len_utf16();
     }
-    tokens
+    Some(c)
   }
 
-  fn lex_keywords(&mut self, iter: &mut Peekable<Iter<char>>, first: char) -> Token {
+  fn text(&self) -> &'a str {
+    &self.code[self.start..self.pos]
+  }
+
+  fn token(&self, ty: TokenType, value: TokenValue) -> Token {
+    Token{ ty, value, line: self.start_line, column: self.start_column, utf16_column: self.start_utf16_column, range: self.start..self.pos }
+  }
+
+  // Reported at the start of the token, with the offending text so far.
+  fn error<T>(&self, message: String) -> Result<T, LexError> {
+    Err(LexError{ message, line: self.start_line, column: self.start_column, range: self.start..self.pos })
+  }
+
+  fn lex_keywords(&mut self, first: char) -> Token {
     let mut keyword = String::from(first);
-    let start_pos = self.pos;
-    let start_line = self.line;
 
-    while iter.peek() != None && (iter.peek().unwrap().is_alphanumeric() || iter.peek().unwrap() == &&'_') {
-      keyword.push(*iter.next().unwrap());
-      self.pos += 1;
+    while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+      keyword.push(self.bump().unwrap());
     }
 
+    let symbol = TokenValue::Symbol(Symbol::intern(&keyword));
     if KEYWORDS.contains(&&*keyword.to_string()) {
-      Token{ ty: TokenType::Keyword(Keyword::new(keyword)), line: start_line, range: Range{ start: start_pos, end: self.pos + 1} }
+      self.token(TokenType::Keyword(Keyword::new(keyword)), symbol)
     } else {
-      Token{ ty: TokenType::Identifier, line: start_line, range: Range{ start: start_pos, end: self.pos + 1} }
+      self.token(TokenType::Identifier, symbol)
     }
   }
 
-  fn lex_string(&mut self, iter: &mut Peekable<Iter<char>>) -> Token {
+  fn lex_string(&mut self) -> Result<Token, LexError> {
     let mut string = String::new();
-    let start_pos = self.pos;
-    let start_line = self.line;
-    let mut end = false;
-
-    while iter.peek() != None {
-      if iter.peek().unwrap() == &&'\"' { 
-        iter.next();
-        end = true; 
-        break;
+
+    loop {
+      match self.bump() {
+        None => return self.error("Unterminated string".to_string()),
+        Some('\"') => break,
+        Some('\\') => {
+          string.push(match self.bump() {
+            None => return self.error("Unterminated string".to_string()),
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(c) => c
+          });
+        },
+        Some(c) => string.push(c)
       }
+    }
+
+    Ok(self.token(TokenType::Literal(Val::String), TokenValue::String(string)))
+  }
+
+  // `// line`, `/* block */` (which nest) and their doc forms `/// line` and `/** block */`.
+  fn lex_comment(&mut self) -> Result<Token, LexError> {
+    let block = self.bump() == Some('*');
+    let doc = if block { self.peek() == Some('*') && self.peek_second() != Some('/') } else { self.peek() == Some('/') };
 
-      if iter.peek().unwrap() == &&'\\' {
-        iter.next();
-        self.pos += 1;
-        self.line_pos += 1;
-
-        if iter.peek().unwrap() == &&'n' {
-            string.push('\\');
-            string.push('n');
-            iter.next();
-            self.pos += 1;
-            self.line += 1;
-            self.line_pos = 0;
-        } else {
-            string.push(*iter.next().unwrap());
-            self.pos += 1;
-            self.line_pos += 1;
+    if ! block {
+      while self.peek().is_some_and(|c| c != '\n') {
+        self.bump();
+      }
+    } else {
+      let mut depth = 1;
+      let mut last = ' ';
+      while depth > 0 {
+        let c = match self.bump() {
+          Some(c) => c,
+          None => return self.error("Unterminated block comment".to_string())
+        };
+
+        if last == '/' && c == '*' {
+          depth += 1;
+          last = ' ';
+          continue;
+        } else if last == '*' && c == '/' {
+          depth -= 1;
+          last = ' ';
+          continue;
         }
-      } else {
-          string.push(*iter.next().unwrap());
-          self.pos += 1;
-          self.line_pos += 1;
+        last = c;
       }
     }
 
-    if iter.peek() == None && end == false {
-      panic!("")
-    }
-    Token{ ty: TokenType::Literal(Val::String), line: start_line, range: Range{ start: start_pos, end: self.pos + 1} }
+    let kind = match (block, doc) {
+      (_, true) => CommentKind::Doc,
+      (true, false) => CommentKind::Block,
+      (false, false) => CommentKind::Line
+    };
+    // Keep only the text, doc blocks also lose the `*` that usually starts each line.
+    let raw = self.text();
+    let text = match kind {
+      CommentKind::Line => &raw[2..],
+      CommentKind::Block => &raw[2..raw.len() - 2],
+      CommentKind::Doc if block => &raw[3..raw.len() - 2],
+      CommentKind::Doc => &raw[3..]
+    };
+    let text = if kind == CommentKind::Doc {
+      text.lines().map(|line| line.trim().trim_start_matches('*').trim()).collect::<Vec<&str>>().join("\n").trim().to_string()
+    } else {
+      text.to_string()
+    };
+    Ok(self.token(TokenType::Comment(kind), TokenValue::String(text)))
   }
 
-  fn lex_operators(&mut self, iter: &mut Peekable<Iter<char>>, first: char) -> Token {
+  // The longest known operator, so `>>` in `Array<Array<Integer>>` is two `>`.
+  fn lex_operators(&mut self, first: char) -> Token {
     let mut op = String::from(first);
-    let start_pos = self.pos;
 
-    while iter.peek() != None && OPEARTORS.contains(&&*iter.peek().unwrap().to_string()) {
-      op.push(*iter.next().unwrap());
-      self.line_pos += 1;
-      self.pos += 1;
+    while self.peek().is_some_and(|c| OPEARTORS.contains(&&*format!("{}{}", op, c))) {
+      op.push(self.bump().unwrap());
     }
+    self.token(TokenType::new(op), TokenValue::None)
+  }
+
+  // `42`, `1_000`, `0xFF`, `0b1010`, `0o17`, `3.14` and `1.5e-3`. `1..5` stops before the range.
+  fn lex_numbers(&mut self, first: char) -> Result<Token, LexError> {
+    let radix = match (first, self.peek()) {
+      ('0', Some('x')) | ('0', Some('X')) => Some(("hexadecimal", 16)),
+      ('0', Some('b')) | ('0', Some('B')) => Some(("binary", 2)),
+      ('0', Some('o')) | ('0', Some('O')) => Some(("octal", 8)),
+      _ => None
+    };
 
-    if ! OPEARTORS.contains(&&*op.to_string()) {
-      panic!("")
+    if let Some((name, radix)) = radix {
+      self.bump();
+      if self.lex_digits(radix)?.is_empty() {
+        return self.error(format!("Missing digits in {} literal [{}]", name, self.text()));
+      }
+      if let Some(c) = self.peek().filter(|c| c.is_alphanumeric()) {
+        return self.error(format!("Invalid digit [{}] in {} literal [{}]", c, name, self.text()));
+      }
     } else {
-      Token{ ty: TokenType::new(op), line: self.line, range: Range{ start: start_pos, end: self.pos + 1} }
+      self.lex_digits(10)?;
+
+      // Only a dot followed by a digit starts a fraction, so `1..5` and `1.method()` keep their dot.
+      if self.peek() == Some('.') && self.peek_second().is_some_and(|c| c.is_ascii_digit()) {
+        self.bump();
+        self.lex_digits(10)?;
+      }
+
+      if self.peek() == Some('e') || self.peek() == Some('E') {
+        self.bump();
+        if self.peek() == Some('+') || self.peek() == Some('-') {
+          self.bump();
+        }
+        if self.lex_digits(10)?.is_empty() {
+          return self.error(format!("Missing exponent digits in [{}]", self.text()));
+        }
+      }
+
+      if self.peek() == Some('.') && self.peek_second().is_some_and(|c| c.is_ascii_digit()) {
+        return self.error(format!("Unexpected second [.] in number literal [{}]", self.text()));
+      }
+      if let Some(c) = self.peek().filter(|c| c.is_alphanumeric()) {
+        return self.error(format!("Invalid character [{}] in number literal [{}]", c, self.text()));
+      }
     }
+
+    let value = Lexer::number_value(&self.text().replace('_', ""));
+    Ok(self.token(TokenType::Literal(Val::Number), value))
   }
 
-  fn lex_numbers(&mut self, iter: &mut Peekable<Iter<char>>, first: char) -> Token {
-    let mut num = String::from(first);
-    let start_pos = self.pos;
+  // Digits of `radix` with `_` allowed between them, returns the ones read without separators.
+  fn lex_digits(&mut self, radix: u32) -> Result<String, LexError> {
+    let mut digits = String::new();
 
-    while iter.peek() != None && (iter.peek().unwrap().is_numeric() || iter.peek().unwrap() == &&'.') {
-      num.push(*iter.next().unwrap());
-      self.line_pos += 1;
-      self.pos += 1
+    while let Some(c) = self.peek() {
+      if c == '_' {
+        if ! self.text().ends_with(|c: char| c.is_digit(radix)) {
+          return self.error(format!("Digit separator [_] must sit between digits in [{}_]", self.text()));
+        }
+      } else if c.is_digit(radix) {
+        digits.push(c);
+      } else {
+        break;
+      }
+      self.bump();
     }
 
-    if num.matches('.').count() > 1 {
-      panic!("")
+    if self.text().ends_with('_') {
+      return self.error(format!("Digit separator [_] must sit between digits in [{}]", self.text()));
     }
+    Ok(digits)
+  }
+
+  // Integers that do not fit in 64 bits become BigIntegers.
+  fn number_value(num: &str) -> TokenValue {
+    let radix = match num.get(..2) {
+      Some("0x") | Some("0X") => 16,
+      Some("0b") | Some("0B") => 2,
+      Some("0o") | Some("0O") => 8,
+      _ => 10
+    };
+
+    if radix != 10 {
+      let digits = &num[2..];
+      match i64::from_str_radix(digits, radix) {
+        Ok(num) => TokenValue::Integer(num),
+        Err(_) => TokenValue::BigInteger(digits.chars().fold(BigInt::from_i64(0), |acc, c| {
+          acc * BigInt::from_i64(radix as i64) + BigInt::from_i64(c.to_digit(radix).unwrap() as i64)
+        }))
+      }
+    } else if num.contains(['.', 'e', 'E']) {
+      TokenValue::Decimal(num.parse::<f64>().unwrap())
+    } else {
+      match num.parse::<i64>() {
+        Ok(num) => TokenValue::Integer(num),
+        Err(_) => TokenValue::BigInteger(BigInt::parse(num).unwrap())
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
 
-    Token{ ty: TokenType::Literal(Val::Number), line: self.line, range: Range{ start: start_pos, end: self.pos + 1} }
+  fn values(code: &str) -> Vec<TokenValue> {
+    Lexer::new(code).map(|token| token.unwrap().value).collect()
   }
-}
\ No newline at end of file
+
+  fn types(code: &str) -> Vec<TokenType> {
+    Lexer::new(code).map(|token| token.unwrap().ty).collect()
+  }
+
+  fn error(code: &str) -> String {
+    match Lexer::new(code).find_map(Result::err) {
+      Some(error) => error.to_string(),
+      None => panic!("[{}] lexed without an error", code)
+    }
+  }
+
+  #[test]
+  fn radix_literals() {
+    assert_eq!(values("0xFF 0Xff 0b1010 0o17 0x7FFF_FFFF"),
+      vec!(TokenValue::Integer(255), TokenValue::Integer(255), TokenValue::Integer(10), TokenValue::Integer(15), TokenValue::Integer(0x7FFF_FFFF)));
+    assert_eq!(values("0xFFFFFFFFFFFFFFFFFF"), vec!(TokenValue::BigInteger(BigInt::parse("4722366482869645213695").unwrap())));
+  }
+
+  #[test]
+  fn digit_separators_and_exponents() {
+    assert_eq!(values("1_000_000 1.5e-3 2E+2 3e4 6.02_2e2_3"), vec!(TokenValue::Integer(1_000_000), TokenValue::Decimal(1.5e-3),
+      TokenValue::Decimal(200.0), TokenValue::Decimal(3e4), TokenValue::Decimal(6.022e23)));
+    assert_eq!(values("99999999999999999999"), vec!(TokenValue::BigInteger(BigInt::parse("99999999999999999999").unwrap())));
+  }
+
+  #[test]
+  fn a_range_keeps_its_dots() {
+    assert_eq!(types("1..5"), vec!(TokenType::Literal(Val::Number), TokenType::DotDot, TokenType::Literal(Val::Number)));
+    assert_eq!(values("1..5"), vec!(TokenValue::Integer(1), TokenValue::None, TokenValue::Integer(5)));
+    assert_eq!(types("1.len()")[..2], [TokenType::Literal(Val::Number), TokenType::Dot]);
+  }
+
+  #[test]
+  fn malformed_literals() {
+    assert_eq!(error("0x"), "Missing digits in hexadecimal literal [0x] on line 1, column 1");
+    assert_eq!(error("a = 0b102"), "Invalid digit [2] in binary literal [0b10] on line 1, column 5");
+    assert_eq!(error("1e"), "Missing exponent digits in [1e] on line 1, column 1");
+    assert_eq!(error("1.2.3"), "Unexpected second [.] in number literal [1.2] on line 1, column 1");
+    assert_eq!(error("12abc"), "Invalid character [a] in number literal [12] on line 1, column 1");
+    assert_eq!(error("1__0"), "Digit separator [_] must sit between digits in [1__] on line 1, column 1");
+    assert_eq!(error("\n1_"), "Digit separator [_] must sit between digits in [1_] on line 2, column 1");
+    assert_eq!(error("\"open"), "Unterminated string on line 1, column 1");
+  }
+
+  #[test]
+  fn the_first_error_ends_the_tokens() {
+    let tokens: Vec<Result<Token, LexError>> = Lexer::new("a 0x b").collect();
+    assert_eq!(tokens.len(), 2);
+    assert!(tokens[1].is_err());
+  }
+
+  #[test]
+  fn block_comments_nest() {
+    let code = "a /* outer /* inner */ still outer */ b";
+    assert_eq!(types(code), vec!(TokenType::Identifier, TokenType::Comment(CommentKind::Block), TokenType::Identifier));
+    assert_eq!(values(code)[1], TokenValue::String(" outer /* inner */ still outer ".to_string()));
+    assert_eq!(error("/* /* */"), "Unterminated block comment on line 1, column 1");
+  }
+
+  #[test]
+  fn comment_kinds() {
+    let code = "// line\n/// doc\n/** doc\n * block\n */\n/**/";
+    assert_eq!(types(code), vec!(TokenType::Comment(CommentKind::Line), TokenType::Newline, TokenType::Comment(CommentKind::Doc), TokenType::Newline,
+      TokenType::Comment(CommentKind::Doc), TokenType::Newline, TokenType::Comment(CommentKind::Block)));
+    let texts: Vec<TokenValue> = values(code).into_iter().filter(|value| *value != TokenValue::None).collect();
+    assert_eq!(texts, vec!(TokenValue::String(" line".to_string()), TokenValue::String("doc".to_string()),
+      TokenValue::String("doc\nblock".to_string()), TokenValue::String(String::new())));
+  }
+
+  #[test]
+  fn positions_count_chars_and_utf16_units() {
+    let tokens: Vec<Token> = Lexer::new("\"😀\" x\n  y").map(Result::unwrap).collect();
+    let positions: Vec<(usize, usize, usize)> = tokens.iter().map(|token| (token.line, token.column, token.utf16_column)).collect();
+    assert_eq!(positions, vec!((0, 0, 0), (0, 4, 5), (0, 5, 6), (1, 2, 2)));
+    assert_eq!(tokens[1].range, 7..8);
+  }
+}
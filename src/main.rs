@@ -1,81 +1,106 @@
 use std::env;
 use std::fs;
+use std::process;
+use std::thread;
 
+#[allow(clippy::module_inception)]
 mod structs;
 mod lexer;
 mod parser;
 mod interpreter;
-
+mod checker;
+mod cst;
+mod bigint;
+mod interner;
+mod source_map;
+mod diagnostic;
+mod natives;
+mod json;
+
+use checker::Checker;
 use interpreter::Interpreter;
-use lexer::Lexer;
-use parser::Parser;
+use cst::SourceFile;
+use source_map::Files;
 use structs::structs::*;
 
+// Every call a script makes takes several Rust frames, this leaves room for interpreter::MAX_CALL_DEPTH
+// of them even in a debug build.
+const STACK_SIZE: usize = 512 * 1024 * 1024;
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        panic!("Forgot to mention file to run.");
+    let runner = thread::Builder::new().stack_size(STACK_SIZE).spawn(run).expect("Could not start the interpreter");
+    // The panic has already been reported.
+    if runner.join().is_err() {
+        process::exit(101);
     }
-    let filename = &args[1];
-    let code = fs::read_to_string(filename).expect("Could not read file");
-
-    let mut lexer = Lexer {
-        code: code.clone(),
-        last_pos: 0,
-        pos: 0,
-        line: 0,
-        line_pos: 0,
-        current_char: None,
-        next_char: None
-    };
-    let tokens: Vec<Token> = lexer.lex();
-
-    let mut parser = Parser {
-        pos: 0,
-        last_pos: tokens.len() - 1,
-        current_token: None,
-        next_token: None,
-        tokens: tokens,
-        source: code
-    };
-    let program: Vec<AST> = parser.parse();
-
-    let mut interpreter = Interpreter { pos: 0, last_pos: program.len() - 1, asts: program, current_ast: None,
-        env: Env::new() };
-    interpreter.interpret();
-
-
-
-
-
-
-
-
-
-
-
+}
 
+const USAGE: &str = "usage: funk-lang [--dump-types] [--no-io] [--seed=N] script.fk [script arguments]";
 
+// Mistakes on the command line are the user's, not a crash.
+fn refuse(message: String) -> ! {
+    eprintln!("error: {}\n{}", message, USAGE);
+    process::exit(1);
+}
 
+fn run() {
+    // funk-lang [--flags] script.fk [script arguments]
+    let args: Vec<String> = env::args().skip(1).collect();
+    let script = match args.iter().position(|arg| ! arg.starts_with("--")) {
+        Some(script) => script,
+        None => refuse("No script to run".to_string())
+    };
+    let flags = &args[..script];
+    for flag in flags {
+        if flag != "--dump-types" && flag != "--no-io" && ! flag.starts_with("--seed=") {
+            refuse(format!("Unknown flag [{}], expected --dump-types, --no-io or --seed=N", flag))
+        }
+    }
+    // `--no-io` keeps scripts away from files and standard input.
+    // `--seed=N` makes the random module repeat itself.
+    let seed = flags.iter().find_map(|flag| flag.strip_prefix("--seed=")).map(|seed| match seed.parse::<u64>() {
+        Ok(seed) => seed,
+        Err(_) => refuse(format!("Invalid seed [{}], expected --seed=N with N a whole number", seed))
+    });
+    let filename = &args[script];
+    let code = match fs::read_to_string(filename) {
+        Ok(code) => code,
+        Err(error) => refuse(format!("Could not read [{}]: {}", filename, error))
+    };
 
+    let mut files = Files::new();
+    let file = files.add(filename, &code);
+    let mut program: Vec<AST> = match SourceFile::parse(&code, file).and_then(|tree| tree.to_ast(file)) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("{}", error.render(&files));
+            process::exit(1);
+        }
+    };
 
-    // let mut parser = Parser {
-    //     pos: 0,
-    //     last_pos: tokens.len() - 1,
-    //     current_token: None,
-    //     next_token: None,
-    //     tokens: tokens,
-    // };
-    // let program: Vec<AST> = parser.parse();
+    let options = natives::Options{ io: ! flags.iter().any(|flag| flag == "--no-io"), args: args[script + 1..].to_vec(), seed };
+    let natives = natives::registry(&options);
+    let mut checker = Checker::new(natives.clone());
+    if let Err(error) = checker.check(&mut program) {
+        eprintln!("{}", error.render(&files));
+        process::exit(1);
+    }
 
-    // let mut interpreter = Interpreter {
-    //     pos: 0,
-    //     next_ast: None,
-    //     current_ast: None,
-    //     last_pos: program.len() - 1,
-    //     asts: program,
-    //     variables: HashMap::default(),
-    // };
+    // `--dump-types` lists what every variable and parameter ended up as, instead of running.
+    if flags.iter().any(|flag| flag == "--dump-types") {
+        for declared in checker.declared {
+            let inferred = if declared.inferred { " (inferred)" } else { "" };
+            println!("{}: {}: {}{}", declared.scope, declared.name, declared.ty, inferred);
+        }
+        return;
+    }
 
-    // interpreter.interpret();
+    let mut interpreter = Interpreter::new(program, files, natives);
+    match interpreter.interpret(options.args) {
+        Ok(code) => process::exit(code),
+        Err(error) => {
+            eprintln!("{}", error.render(&interpreter.files));
+            process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,767 @@
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::cell::RefCell;
+use std::process;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use bigint::BigInt;
+use json;
+use structs::structs::*;
+
+// What a run is allowed to do, from the command line flags.
+pub struct Options {
+  // Off with `--no-io`, the io natives still type check but throw when called.
+  pub io: bool,
+  // What followed the script on the command line.
+  pub args: Vec<String>,
+  // From `--seed=N`, for the same random numbers on every run.
+  pub seed: Option<u64>
+}
+
+impl Default for Options {
+  fn default() -> Options {
+    Options{ io: true, args: vec!(), seed: None }
+  }
+}
+
+// The natives every program starts with, top-level ones in `funcs` and each module in `mods`.
+pub fn registry(options: &Options) -> Env {
+  let mut env = Env::new();
+
+  define(&mut env, "", "wrapping_add", vec!(Type::Integer, Type::Integer), Type::Integer, |args| match args {
+    [Object::Integer(a), Object::Integer(b)] => Ok(Object::Integer(Integer{ value: a.value.wrapping_add(b.value) })),
+    _ => Err(invalid("wrapping_add", args))
+  });
+  define(&mut env, "", "wrapping_sub", vec!(Type::Integer, Type::Integer), Type::Integer, |args| match args {
+    [Object::Integer(a), Object::Integer(b)] => Ok(Object::Integer(Integer{ value: a.value.wrapping_sub(b.value) })),
+    _ => Err(invalid("wrapping_sub", args))
+  });
+  define(&mut env, "", "wrapping_mul", vec!(Type::Integer, Type::Integer), Type::Integer, |args| match args {
+    [Object::Integer(a), Object::Integer(b)] => Ok(Object::Integer(Integer{ value: a.value.wrapping_mul(b.value) })),
+    _ => Err(invalid("wrapping_mul", args))
+  });
+  define(&mut env, "", "to_big", vec!(Type::Integer), Type::BigInteger, |args| match args {
+    [Object::Integer(a)] => Ok(Object::BigInteger(BigInteger{ value: BigInt::from_i64(a.value) })),
+    _ => Err(invalid("to_big", args))
+  });
+  // null when the value does not fit.
+  define(&mut env, "", "to_integer", vec!(Type::BigInteger), Type::Nullable(Box::new(Type::Integer)), |args| match args {
+    [Object::BigInteger(a)] => Ok(a.value.to_i64().map_or(Object::Null, |value| Object::Integer(Integer{ value }))),
+    _ => Err(invalid("to_integer", args))
+  });
+  // Builds the builtin Error klass, which has a trace the caller does not pass.
+  define(&mut env, "", "Error", vec!(Type::String), Type::Named("Error".to_string(), vec!()), |args| match args {
+    [Object::String(message)] => Ok(Object::error(message.value.clone(), String::new())),
+    _ => Err(invalid("Error", args))
+  });
+
+  define_variadic(&mut env, "", "print", vec!(), Type::Void, |args| {
+    print!("{}", join(args));
+    // Without a newline nothing would show until the buffer fills.
+    let _ = io::stdout().flush();
+    Ok(Object::Void)
+  });
+  define_variadic(&mut env, "", "println", vec!(), Type::Void, |args| {
+    println!("{}", join(args));
+    Ok(Object::Void)
+  });
+  define_variadic(&mut env, "", "eprintln", vec!(), Type::Void, |args| {
+    eprintln!("{}", join(args));
+    Ok(Object::Void)
+  });
+  // format("{} is {:.2}", name, ratio)
+  define_variadic(&mut env, "", "format", vec!(Type::String), Type::String, |args| match args {
+    [Object::String(template), rest @ ..] => format(&template.value, rest).map(|value| Object::String(Str{ value })),
+    _ => Err(invalid("format", args))
+  });
+
+  math(&mut env);
+  strings(&mut env);
+  files(&mut env, options.io);
+  sys(&mut env, options.args.clone());
+  time(&mut env);
+  random(&mut env, options.seed.unwrap_or_else(clock_seed));
+  json_module(&mut env);
+  env
+}
+
+fn json_module(env: &mut Env) {
+  // Hash, Array, String, Integer, Decimal, Boolean or null, depending on the text.
+  define(env, "json", "parse", vec!(Type::String), Type::Any, |args| match args {
+    [Object::String(text)] => json::parse(&text.value),
+    _ => Err(invalid("json::parse", args))
+  });
+  define(env, "json", "stringify", vec!(Type::Any), Type::String, |args| match args {
+    [value] => json::stringify(value, None).map(string),
+    _ => Err(invalid("json::stringify", args))
+  });
+  // Two spaces per level.
+  define(env, "json", "pretty", vec!(Type::Any), Type::String, |args| match args {
+    [value] => json::stringify(value, Some(2)).map(string),
+    _ => Err(invalid("json::pretty", args))
+  });
+}
+
+fn sys(env: &mut Env, args: Vec<String>) {
+  define(env, "sys", "args", vec!(), Type::Array(Box::new(Type::String)), move |_| {
+    Ok(Object::Array(args.iter().map(|arg| string(arg.clone())).collect()))
+  });
+  // null when the variable is not set or not unicode.
+  define(env, "sys", "env", vec!(Type::String), Type::Nullable(Box::new(Type::String)), |args| match args {
+    [Object::String(name)] => Ok(env::var(&name.value).map_or(Object::Null, string)),
+    _ => Err(invalid("sys::env", args))
+  });
+  // Stops right away, `finally` blocks do not run.
+  define(env, "sys", "exit", vec!(Type::Integer), Type::Void, |args| match args {
+    [Object::Integer(code)] => match i32::try_from(code.value) {
+      Ok(code) => {
+        let _ = io::stdout().flush();
+        process::exit(code)
+      },
+      Err(_) => Err(format!("Exit code [{}] is out of range", code.value))
+    },
+    _ => Err(invalid("sys::exit", args))
+  });
+}
+
+// Durations are Decimal seconds, so they add and subtract like any other number.
+fn time(env: &mut Env) {
+  let start = Instant::now();
+  // Seconds since the program started, never goes backwards.
+  define(env, "time", "now", vec!(), Type::Decimal, move |_| Ok(decimal(start.elapsed().as_secs_f64())));
+  define(env, "time", "elapsed", vec!(Type::Decimal), Type::Decimal, move |args| match args {
+    [Object::Decimal(since)] => Ok(decimal(start.elapsed().as_secs_f64() - since.value)),
+    _ => Err(invalid("time::elapsed", args))
+  });
+  // Wall clock, seconds since 1970-01-01 UTC. Can jump when the system clock is set.
+  define(env, "time", "timestamp", vec!(), Type::Decimal, |_| Ok(decimal(since_epoch().as_secs_f64())));
+  define(env, "time", "timestamp_millis", vec!(), Type::Integer, |_| {
+    Ok(Object::Integer(Integer{ value: since_epoch().as_millis() as i64 }))
+  });
+  define(env, "time", "sleep", vec!(Type::Decimal), Type::Void, |args| match args {
+    [Object::Decimal(seconds)] => match Duration::try_from_secs_f64(seconds.value) {
+      Ok(duration) => {
+        thread::sleep(duration);
+        Ok(Object::Void)
+      },
+      Err(_) => Err(format!("Cannot sleep for [{:?}] seconds", seconds.value))
+    },
+    _ => Err(invalid("time::sleep", args))
+  });
+  define(env, "time", "to_millis", vec!(Type::Decimal), Type::Integer, |args| match args {
+    [Object::Decimal(seconds)] => match to_integer((seconds.value * 1000.0).round()) {
+      Some(value) => Ok(Object::Integer(Integer{ value })),
+      None => Err(format!("[{:?}] seconds do not fit in Integer milliseconds", seconds.value))
+    },
+    _ => Err(invalid("time::to_millis", args))
+  });
+  define(env, "time", "from_millis", vec!(Type::Integer), Type::Decimal, |args| match args {
+    [Object::Integer(millis)] => Ok(decimal(millis.value as f64 / 1000.0)),
+    _ => Err(invalid("time::from_millis", args))
+  });
+}
+
+fn since_epoch() -> Duration {
+  SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+fn clock_seed() -> u64 {
+  since_epoch().as_nanos() as u64 ^ ((process::id() as u64) << 32)
+}
+
+// All the natives share one generator, so a seed fixes every number the program draws.
+fn random(env: &mut Env, seed: u64) {
+  let rng = Rc::new(RefCell::new(Rng::new(seed)));
+
+  let shared = rng.clone();
+  define(env, "random", "seed", vec!(Type::Integer), Type::Void, move |args| match args {
+    [Object::Integer(seed)] => {
+      *shared.borrow_mut() = Rng::new(seed.value as u64);
+      Ok(Object::Void)
+    },
+    _ => Err(invalid("random::seed", args))
+  });
+  // From `min` up to, not including, `max`.
+  let shared = rng.clone();
+  define(env, "random", "int_range", vec!(Type::Integer, Type::Integer), Type::Integer, move |args| match args {
+    [Object::Integer(min), Object::Integer(max)] if min.value >= max.value => {
+      Err(format!("[random::int_range] needs min below max, found [{}, {})", min.value, max.value))
+    },
+    [Object::Integer(min), Object::Integer(max)] => {
+      let span = max.value.wrapping_sub(min.value) as u64;
+      Ok(Object::Integer(Integer{ value: min.value.wrapping_add(shared.borrow_mut().below(span) as i64) }))
+    },
+    _ => Err(invalid("random::int_range", args))
+  });
+  // From 0.0 up to, not including, 1.0.
+  let shared = rng.clone();
+  define(env, "random", "decimal", vec!(), Type::Decimal, move |_| Ok(decimal(shared.borrow_mut().unit())));
+  // Generic over the element type T.
+  let item = || Type::Param("T".to_string());
+  let items = || Type::Array(Box::new(item()));
+  // Any element of an Array.
+  let shared = rng.clone();
+  define(env, "random", "choice", vec!(items()), item(), move |args| match args {
+    [Object::Array(items)] if items.is_empty() => Err("[random::choice] needs a non-empty Array".to_string()),
+    [Object::Array(items)] => Ok(items[shared.borrow_mut().below(items.len() as u64) as usize].clone()),
+    _ => Err(invalid("random::choice", args))
+  });
+  // A shuffled copy of an Array.
+  define(env, "random", "shuffle", vec!(items()), items(), move |args| match args {
+    [Object::Array(items)] => {
+      let mut items = items.clone();
+      let mut rng = rng.borrow_mut();
+      for i in (1..items.len()).rev() {
+        items.swap(i, rng.below(i as u64 + 1) as usize);
+      }
+      Ok(Object::Array(items))
+    },
+    _ => Err(invalid("random::shuffle", args))
+  });
+}
+
+fn math(env: &mut Env) {
+  define(env, "math", "PI", vec!(), Type::Decimal, |_| Ok(decimal(std::f64::consts::PI)));
+  define(env, "math", "E", vec!(), Type::Decimal, |_| Ok(decimal(std::f64::consts::E)));
+
+  // abs, min and max are overloaded for Integers and Decimals, and give back the same.
+  define(env, "math", "abs", vec!(Type::Integer), Type::Integer, |args| match args {
+    [Object::Integer(i)] => i.value.checked_abs().map(|value| Object::Integer(Integer{ value }))
+      .ok_or_else(|| format!("Integer overflow in [math::abs({})]", i.value)),
+    _ => Err(invalid("math::abs", args))
+  });
+  define(env, "math", "abs", vec!(Type::Decimal), Type::Decimal, |args| match args {
+    [Object::Decimal(d)] => Ok(decimal(d.value.abs())),
+    _ => Err(invalid("math::abs", args))
+  });
+  define(env, "math", "min", vec!(Type::Integer, Type::Integer), Type::Integer, |args| match args {
+    [Object::Integer(a), Object::Integer(b)] => Ok(Object::Integer(Integer{ value: a.value.min(b.value) })),
+    _ => Err(invalid("math::min", args))
+  });
+  define(env, "math", "min", vec!(Type::Decimal, Type::Decimal), Type::Decimal, |args| match args {
+    [Object::Decimal(a), Object::Decimal(b)] => Ok(decimal(a.value.min(b.value))),
+    _ => Err(invalid("math::min", args))
+  });
+  define(env, "math", "max", vec!(Type::Integer, Type::Integer), Type::Integer, |args| match args {
+    [Object::Integer(a), Object::Integer(b)] => Ok(Object::Integer(Integer{ value: a.value.max(b.value) })),
+    _ => Err(invalid("math::max", args))
+  });
+  define(env, "math", "max", vec!(Type::Decimal, Type::Decimal), Type::Decimal, |args| match args {
+    [Object::Decimal(a), Object::Decimal(b)] => Ok(decimal(a.value.max(b.value))),
+    _ => Err(invalid("math::max", args))
+  });
+
+  unary(env, "floor", f64::floor);
+  unary(env, "ceil", f64::ceil);
+  // Halves round away from zero.
+  unary(env, "round", f64::round);
+  unary(env, "exp", f64::exp);
+  unary(env, "sin", f64::sin);
+  unary(env, "cos", f64::cos);
+  unary(env, "tan", f64::tan);
+  unary(env, "atan", f64::atan);
+  // Outside their domain these are errors rather than NaN.
+  partial(env, "sqrt", |x| x >= 0.0, f64::sqrt);
+  partial(env, "ln", |x| x > 0.0, f64::ln);
+  partial(env, "log10", |x| x > 0.0, f64::log10);
+  partial(env, "asin", |x| (-1.0..=1.0).contains(&x), f64::asin);
+  partial(env, "acos", |x| (-1.0..=1.0).contains(&x), f64::acos);
+  define(env, "math", "atan2", vec!(Type::Decimal, Type::Decimal), Type::Decimal, |args| match args {
+    [Object::Decimal(y), Object::Decimal(x)] => Ok(decimal(y.value.atan2(x.value))),
+    _ => Err(invalid("math::atan2", args))
+  });
+  define(env, "math", "pow", vec!(Type::Decimal, Type::Decimal), Type::Decimal, |args| match args {
+    [Object::Decimal(base), Object::Decimal(exponent)] => Ok(decimal(base.value.powf(exponent.value))),
+    _ => Err(invalid("math::pow", args))
+  });
+
+  define(env, "math", "gcd", vec!(Type::Integer, Type::Integer), Type::Integer, |args| match args {
+    [Object::Integer(a), Object::Integer(b)] => gcd(a.value, b.value).map(|value| Object::Integer(Integer{ value }))
+      .ok_or_else(|| format!("Integer overflow in [math::gcd({}, {})]", a.value, b.value)),
+    _ => Err(invalid("math::gcd", args))
+  });
+  define(env, "math", "lcm", vec!(Type::Integer, Type::Integer), Type::Integer, |args| match args {
+    [Object::Integer(a), Object::Integer(b)] => lcm(a.value, b.value).map(|value| Object::Integer(Integer{ value }))
+      .ok_or_else(|| format!("Integer overflow in [math::lcm({}, {})]", a.value, b.value)),
+    _ => Err(invalid("math::lcm", args))
+  });
+
+  // null unless the value converts exactly, round a Decimal first to drop its fraction.
+  define(env, "math", "to_integer", vec!(Type::Decimal), Type::Nullable(Box::new(Type::Integer)), |args| match args {
+    [Object::Decimal(d)] => Ok(to_integer(d.value).map_or(Object::Null, |value| Object::Integer(Integer{ value }))),
+    _ => Err(invalid("math::to_integer", args))
+  });
+  define(env, "math", "to_decimal", vec!(Type::Integer), Type::Nullable(Box::new(Type::Decimal)), |args| match args {
+    [Object::Integer(i)] => Ok(match i.value as f64 {
+      value if to_integer(value) == Some(i.value) => decimal(value),
+      _ => Object::Null
+    }),
+    _ => Err(invalid("math::to_decimal", args))
+  });
+}
+
+// Methods on String values, which arrive as the first argument. Indices count chars, not bytes.
+fn strings(env: &mut Env) {
+  let nullable = |ty: Type| Type::Nullable(Box::new(ty));
+  let strings = || Type::Array(Box::new(Type::String));
+
+  method(env, "len", vec!(), Type::Integer, |s, _| Ok(Object::Integer(Integer{ value: s.chars().count() as i64 })));
+  method(env, "chars", vec!(), strings(), |s, _| Ok(Object::Array(s.chars().map(|c| string(c.to_string())).collect())));
+  method(env, "split", vec!(Type::String), strings(), |s, args| match args {
+    [Object::String(separator)] if separator.value.is_empty() => Err("[String::split] needs a separator, use chars() for single chars".to_string()),
+    [Object::String(separator)] => Ok(Object::Array(s.split(separator.value.as_str()).map(|part| string(part.to_string())).collect())),
+    _ => Err(invalid("String::split", args))
+  });
+  method(env, "trim", vec!(), Type::String, |s, _| Ok(string(s.trim().to_string())));
+  method(env, "replace", vec!(Type::String, Type::String), Type::String, |s, args| match args {
+    [Object::String(from), Object::String(to)] => Ok(string(s.replace(from.value.as_str(), &to.value))),
+    _ => Err(invalid("String::replace", args))
+  });
+  method(env, "to_upper", vec!(), Type::String, |s, _| Ok(string(s.to_uppercase())));
+  method(env, "to_lower", vec!(), Type::String, |s, _| Ok(string(s.to_lowercase())));
+  method(env, "starts_with", vec!(Type::String), Type::Boolean, |s, args| match args {
+    [Object::String(prefix)] => Ok(Object::Boolean(s.starts_with(prefix.value.as_str()))),
+    _ => Err(invalid("String::starts_with", args))
+  });
+  method(env, "ends_with", vec!(Type::String), Type::Boolean, |s, args| match args {
+    [Object::String(suffix)] => Ok(Object::Boolean(s.ends_with(suffix.value.as_str()))),
+    _ => Err(invalid("String::ends_with", args))
+  });
+  // Where `needle` first starts, null when it does not occur.
+  method(env, "find", vec!(Type::String), nullable(Type::Integer), |s, args| match args {
+    [Object::String(needle)] => Ok(match s.find(needle.value.as_str()) {
+      Some(byte) => Object::Integer(Integer{ value: s[..byte].chars().count() as i64 }),
+      None => Object::Null
+    }),
+    _ => Err(invalid("String::find", args))
+  });
+  // From `start` up to, not including, `end`.
+  method(env, "substring", vec!(Type::Integer, Type::Integer), Type::String, |s, args| match args {
+    [Object::Integer(start), Object::Integer(end)] => {
+      let len = s.chars().count() as i64;
+      if start.value < 0 || start.value > end.value || end.value > len {
+        return Err(format!("[String::substring] range [{}, {}) is outside a string of length {}", start.value, end.value, len));
+      }
+      Ok(string(s.chars().skip(start.value as usize).take((end.value - start.value) as usize).collect()))
+    },
+    _ => Err(invalid("String::substring", args))
+  });
+  method(env, "repeat", vec!(Type::Integer), Type::String, |s, args| match args {
+    [Object::Integer(count)] if count.value < 0 => Err(format!("[String::repeat] cannot repeat {} times", count.value)),
+    [Object::Integer(count)] => Ok(string(s.repeat(count.value as usize))),
+    _ => Err(invalid("String::repeat", args))
+  });
+  // null when the text is not a number, surrounding whitespace is allowed.
+  method(env, "to_integer", vec!(), nullable(Type::Integer), |s, _| Ok(match s.trim().parse::<i64>() {
+    Ok(value) => Object::Integer(Integer{ value }),
+    Err(_) => Object::Null
+  }));
+  method(env, "to_decimal", vec!(), nullable(Type::Decimal), |s, _| Ok(match s.trim().parse::<f64>() {
+    Ok(value) if value.is_finite() => decimal(value),
+    _ => Object::Null
+  }));
+}
+
+// The io module. Failures throw an Error naming the path.
+fn files(env: &mut Env, enabled: bool) {
+  let strings = || Type::Array(Box::new(Type::String));
+
+  io(env, enabled, "read_file", vec!(Type::String), Type::String, |args| match args {
+    [Object::String(path)] => fs::read_to_string(&path.value).map(string).map_err(|e| failed("read", &path.value, e)),
+    _ => Err(invalid("io::read_file", args))
+  });
+  // Lines without their line endings.
+  io(env, enabled, "read_lines", vec!(Type::String), strings(), |args| match args {
+    [Object::String(path)] => match fs::read_to_string(&path.value) {
+      Ok(text) => Ok(Object::Array(text.lines().map(|line| string(line.to_string())).collect())),
+      Err(e) => Err(failed("read", &path.value, e))
+    },
+    _ => Err(invalid("io::read_lines", args))
+  });
+  io(env, enabled, "write_file", vec!(Type::String, Type::String), Type::Void, |args| match args {
+    [Object::String(path), Object::String(text)] => fs::write(&path.value, &text.value).map(|_| Object::Void).map_err(|e| failed("write", &path.value, e)),
+    _ => Err(invalid("io::write_file", args))
+  });
+  io(env, enabled, "append_file", vec!(Type::String, Type::String), Type::Void, |args| match args {
+    [Object::String(path), Object::String(text)] => fs::OpenOptions::new().append(true).create(true).open(&path.value)
+      .and_then(|mut file| file.write_all(text.value.as_bytes()))
+      .map(|_| Object::Void).map_err(|e| failed("append to", &path.value, e)),
+    _ => Err(invalid("io::append_file", args))
+  });
+  // A line from standard input without its line ending, null once the input ends.
+  io(env, enabled, "read_line", vec!(), Type::Nullable(Box::new(Type::String)), |_| {
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+      Ok(0) => Ok(Object::Null),
+      Ok(_) => Ok(string(line.trim_end_matches(['\n', '\r']).to_string())),
+      Err(e) => Err(failed("read", "standard input", e))
+    }
+  });
+  // Entry names, sorted.
+  io(env, enabled, "list_dir", vec!(Type::String), strings(), |args| match args {
+    [Object::String(path)] => {
+      let entries = fs::read_dir(&path.value).map_err(|e| failed("list", &path.value, e))?;
+      let mut names = vec!();
+      for entry in entries {
+        names.push(entry.map_err(|e| failed("list", &path.value, e))?.file_name().to_string_lossy().into_owned());
+      }
+      names.sort();
+      Ok(Object::Array(names.into_iter().map(string).collect()))
+    },
+    _ => Err(invalid("io::list_dir", args))
+  });
+  // With any missing parents, fine if it already exists.
+  io(env, enabled, "create_dir", vec!(Type::String), Type::Void, |args| match args {
+    [Object::String(path)] => fs::create_dir_all(&path.value).map(|_| Object::Void).map_err(|e| failed("create", &path.value, e)),
+    _ => Err(invalid("io::create_dir", args))
+  });
+  io(env, enabled, "exists", vec!(Type::String), Type::Boolean, |args| match args {
+    [Object::String(path)] => Ok(Object::Boolean(fs::metadata(&path.value).is_ok())),
+    _ => Err(invalid("io::exists", args))
+  });
+  io(env, enabled, "is_dir", vec!(Type::String), Type::Boolean, |args| match args {
+    [Object::String(path)] => Ok(Object::Boolean(fs::metadata(&path.value).is_ok_and(|meta| meta.is_dir()))),
+    _ => Err(invalid("io::is_dir", args))
+  });
+}
+
+fn io<F>(env: &mut Env, enabled: bool, name: &'static str, params: Vec<Type>, return_type: Type, func: F)
+  where F: Fn(&[Object]) -> Result<Object, String> + 'static {
+  if enabled {
+    define(env, "io", name, params, return_type, func);
+  } else {
+    define(env, "io", name, params, return_type, move |_| Err(format!("[io::{}] is disabled by --no-io", name)));
+  }
+}
+
+fn failed(action: &str, path: &str, error: io::Error) -> String {
+  format!("Could not {} [{}]: {}", action, path, error)
+}
+
+// Adds a method to String, `func` gets the string and the remaining arguments.
+fn method<F>(env: &mut Env, name: &str, params: Vec<Type>, return_type: Type, func: F)
+  where F: Fn(&str, &[Object]) -> Result<Object, String> + 'static {
+  let full = format!("String::{}", name);
+  let params = [vec!(Type::String), params].concat();
+  define(env, "String", name, params, return_type, move |args| match args {
+    [Object::String(s), rest @ ..] => func(&s.value, rest),
+    _ => Err(invalid(&full, args))
+  });
+}
+
+fn string(value: String) -> Object {
+  Object::String(Str{ value })
+}
+
+// A Decimal function defined everywhere.
+fn unary(env: &mut Env, name: &'static str, f: fn(f64) -> f64) {
+  partial(env, name, |_| true, f);
+}
+
+fn partial(env: &mut Env, name: &'static str, domain: fn(f64) -> bool, f: fn(f64) -> f64) {
+  define(env, "math", name, vec!(Type::Decimal), Type::Decimal, move |args| match args {
+    [Object::Decimal(d)] if domain(d.value) => Ok(decimal(f(d.value))),
+    [Object::Decimal(d)] => Err(format!("[math::{}] is not defined for [{:?}]", name, d.value)),
+    _ => Err(invalid(&format!("math::{}", name), args))
+  });
+}
+
+fn decimal(value: f64) -> Object {
+  Object::Decimal(Decimal{ value })
+}
+
+// None when the result does not fit, as for gcd(i64::MIN, 0).
+fn gcd(a: i64, b: i64) -> Option<i64> {
+  let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+  while b != 0 {
+    (a, b) = (b, a % b);
+  }
+  if a > i64::MAX as u64 { None } else { Some(a as i64) }
+}
+
+fn lcm(a: i64, b: i64) -> Option<i64> {
+  if a == 0 || b == 0 {
+    return Some(0);
+  }
+  (a / gcd(a, b)?).checked_mul(b)?.checked_abs()
+}
+
+// Whole numbers in range only. 2^63 itself rounds up from i64::MAX so the bound is exclusive.
+fn to_integer(value: f64) -> Option<i64> {
+  if value.fract() != 0.0 || ! (-9223372036854775808.0..9223372036854775808.0).contains(&value) {
+    return None;
+  }
+  Some(value as i64)
+}
+
+// Adds `name` to `module`, or to the top level when `module` is empty. Defining a name again with other
+// parameters overloads it.
+pub fn define<F>(env: &mut Env, module: &str, name: &str, params: Vec<Type>, return_type: Type, func: F)
+  where F: Fn(&[Object]) -> Result<Object, String> + 'static {
+  add(env, module, name, params, false, return_type, Rc::new(func));
+}
+
+// Like `define`, taking any number of values after `params`.
+pub fn define_variadic<F>(env: &mut Env, module: &str, name: &str, params: Vec<Type>, return_type: Type, func: F)
+  where F: Fn(&[Object]) -> Result<Object, String> + 'static {
+  add(env, module, name, params, true, return_type, Rc::new(func));
+}
+
+fn add(env: &mut Env, module: &str, name: &str, params: Vec<Type>, variadic: bool, return_type: Type, func: NativeFn) {
+  let full = if module.is_empty() { name.to_string() } else { format!("{}::{}", module, name) };
+  let native = Native{ name: full, params, variadic, return_type, func, overloads: vec!() };
+  let funcs = if module.is_empty() {
+    &mut env.funcs
+  } else {
+    let members = env.mods.entry(module.to_string())
+      .or_insert_with(|| Object::Module(Module{ path: module.to_string(), env: Env::new() }));
+    match members {
+      Object::Module(members) => &mut members.env.funcs,
+      _ => return
+    }
+  };
+  match funcs.get_mut(name) {
+    Some(Object::Native(first)) => first.overloads.push(native),
+    _ => { funcs.insert(name.to_string(), Object::Native(native)); }
+  }
+}
+
+// The interpreter checks types first, so this only shows up for values a native does not handle.
+fn invalid(name: &str, args: &[Object]) -> String {
+  let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+  format!("Invalid arguments to [{}]: {}", name, args.join(", "))
+}
+
+// SplitMix64, small and fast with good enough output for scripts. Not for anything secret.
+pub struct Rng {
+  state: u64
+}
+
+impl Rng {
+  pub fn new(seed: u64) -> Rng {
+    Rng{ state: seed }
+  }
+
+  pub fn next_u64(&mut self) -> u64 {
+    self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = self.state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+  }
+
+  // Uniform in [0, bound), rejecting the top values that would favour small results. 0 means every u64.
+  pub fn below(&mut self, bound: u64) -> u64 {
+    if bound == 0 {
+      return self.next_u64();
+    }
+    let zone = u64::MAX - u64::MAX % bound;
+    loop {
+      let value = self.next_u64();
+      if value < zone {
+        return value % bound;
+      }
+    }
+  }
+
+  // Uniform in [0, 1), from the top 53 bits.
+  pub fn unit(&mut self) -> f64 {
+    (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+  }
+}
+
+// Values as `print` shows them, separated by spaces.
+fn join(values: &[Object]) -> String {
+  values.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(" ")
+}
+
+// Fills each `{}` or `{:.N}` with the next value, `{{` and `}}` are literal braces.
+fn format(template: &str, args: &[Object]) -> Result<String, String> {
+  let mut out = String::new();
+  let mut args = args.iter();
+  let mut chars = template.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '{' if chars.peek() == Some(&'{') => { chars.next(); out.push('{'); },
+      '}' if chars.peek() == Some(&'}') => { chars.next(); out.push('}'); },
+      '{' => {
+        let spec: String = chars.by_ref().take_while(|c| *c != '}').collect();
+        let arg = match args.next() {
+          Some(arg) => arg,
+          None => return Err(format!("[format] has more placeholders than values in [{}]", template))
+        };
+        match spec.as_str() {
+          "" => out.push_str(&arg.to_string()),
+          _ => {
+            let precision = match spec.strip_prefix(":.").and_then(|digits| digits.parse::<usize>().ok()) {
+              Some(precision) => precision,
+              None => return Err(format!("Unknown format [{{{}}}], expected {{}} or {{:.N}}", spec))
+            };
+            match arg {
+              Object::Decimal(d) => out.push_str(&format!("{:.*}", precision, d.value)),
+              Object::Integer(i) => out.push_str(&format!("{:.*}", precision, i.value as f64)),
+              other => return Err(format!("[{{{}}}] needs a number, found [{}]", spec, other))
+            }
+          }
+        }
+      },
+      '}' => return Err(format!("Unmatched [}}] in [{}], write [}}}}] for a literal one", template)),
+      c => out.push(c)
+    }
+  }
+  match args.len() {
+    0 => Ok(out),
+    left => Err(format!("[format] was given {} more values than placeholders in [{}]", left, template))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn string(value: &str) -> Object {
+    Object::String(Str{ value: value.to_string() })
+  }
+
+  #[test]
+  fn modules_keep_their_natives_apart() {
+    let mut env = registry(&Options::default());
+    define(&mut env, "demo", "twice", vec!(Type::Integer), Type::Integer, |args| match args {
+      [Object::Integer(i)] => Ok(Object::Integer(Integer{ value: i.value * 2 })),
+      _ => Err(invalid("demo::twice", args))
+    });
+
+    let twice = env.get_native(Some("demo"), "twice").unwrap();
+    assert_eq!((twice.name.as_str(), twice.variadic), ("demo::twice", false));
+    assert_eq!((twice.func)(&[Object::Integer(Integer{ value: 21 })]), Ok(Object::Integer(Integer{ value: 42 })));
+    assert!(env.get_native(None, "twice").is_none());
+    assert!(env.get_native(Some("demo"), "println").is_none());
+    assert!(env.get_native(None, "println").unwrap().variadic);
+  }
+
+  #[test]
+  fn defining_a_name_again_overloads_it() {
+    let env = registry(&Options::default());
+    let abs = env.get_native(Some("math"), "abs").unwrap();
+    let params: Vec<Vec<Type>> = abs.candidates().iter().map(|native| native.params.clone()).collect();
+    assert_eq!(params, vec!(vec!(Type::Integer), vec!(Type::Decimal)));
+    assert_eq!((abs.overloads[0].func)(&[decimal(-2.5)]), Ok(decimal(2.5)));
+    assert_eq!((abs.func)(&[Object::Integer(Integer{ value: -3 })]), Ok(Object::Integer(Integer{ value: 3 })));
+  }
+
+  // Calls the String method `name` on `s`.
+  fn call(env: &Env, s: &str, name: &str, args: &[Object]) -> Result<Object, String> {
+    let native = env.get_native(Some("String"), name).unwrap();
+    (native.func)(&[vec!(string(s)), args.to_vec()].concat())
+  }
+
+  fn integer(value: i64) -> Object {
+    Object::Integer(Integer{ value })
+  }
+
+  #[test]
+  fn string_methods_count_chars() {
+    let env = registry(&Options::default());
+    assert_eq!(call(&env, "héllo", "len", &[]), Ok(integer(5)));
+    assert_eq!(call(&env, "día", "chars", &[]), Ok(Object::Array(vec!(string("d"), string("í"), string("a")))));
+    assert_eq!(call(&env, "añob", "find", &[string("b")]), Ok(integer(3)));
+    assert_eq!(call(&env, "añob", "find", &[string("z")]), Ok(Object::Null));
+    assert_eq!(call(&env, "añob", "substring", &[integer(1), integer(3)]), Ok(string("ño")));
+    assert_eq!(call(&env, "añob", "substring", &[integer(4), integer(4)]), Ok(string("")));
+  }
+
+  #[test]
+  fn string_methods_refuse_what_makes_no_sense() {
+    let env = registry(&Options::default());
+    assert_eq!(call(&env, "abc", "substring", &[integer(2), integer(1)]),
+      Err("[String::substring] range [2, 1) is outside a string of length 3".to_string()));
+    assert_eq!(call(&env, "abc", "substring", &[integer(0), integer(4)]),
+      Err("[String::substring] range [0, 4) is outside a string of length 3".to_string()));
+    assert_eq!(call(&env, "abc", "repeat", &[integer(-1)]), Err("[String::repeat] cannot repeat -1 times".to_string()));
+    assert_eq!(call(&env, "abc", "split", &[string("")]),
+      Err("[String::split] needs a separator, use chars() for single chars".to_string()));
+    assert_eq!(call(&env, "a,,b", "split", &[string(",")]), Ok(Object::Array(vec!(string("a"), string(""), string("b")))));
+  }
+
+  #[test]
+  fn strings_parse_to_numbers_or_null() {
+    let env = registry(&Options::default());
+    assert_eq!(call(&env, " 42\n", "to_integer", &[]), Ok(integer(42)));
+    assert_eq!(call(&env, "-7", "to_integer", &[]), Ok(integer(-7)));
+    assert_eq!(call(&env, "4.2", "to_integer", &[]), Ok(Object::Null));
+    assert_eq!(call(&env, "99999999999999999999", "to_integer", &[]), Ok(Object::Null));
+    assert_eq!(call(&env, "", "to_integer", &[]), Ok(Object::Null));
+    assert_eq!(call(&env, "1.5e3", "to_decimal", &[]), Ok(decimal(1500.0)));
+    assert_eq!(call(&env, "inf", "to_decimal", &[]), Ok(Object::Null));
+    assert_eq!(call(&env, "one", "to_decimal", &[]), Ok(Object::Null));
+  }
+
+  #[test]
+  fn no_io_keeps_the_signatures_but_refuses_to_run() {
+    let open = registry(&Options::default());
+    let closed = registry(&Options{ io: false, ..Options::default() });
+    for name in ["read_file", "read_lines", "write_file", "append_file", "read_line", "list_dir", "create_dir", "exists", "is_dir"] {
+      let (open, closed) = (open.get_native(Some("io"), name).unwrap(), closed.get_native(Some("io"), name).unwrap());
+      assert_eq!((&closed.params, &closed.return_type), (&open.params, &open.return_type), "{}", name);
+      let args: Vec<Object> = closed.params.iter().map(|_| string("io_unit_test")).collect();
+      assert_eq!((closed.func)(&args), Err(format!("[io::{}] is disabled by --no-io", name)));
+    }
+  }
+
+  #[test]
+  fn io_failures_name_the_path() {
+    let env = registry(&Options::default());
+    let io = |name| env.get_native(Some("io"), name).unwrap().func.clone();
+    let dir = std::env::temp_dir().join(format!("funk_io_{}", std::process::id()));
+    let path = |name: &str| string(&dir.join(name).to_string_lossy());
+    let file = dir.join("notes.txt").to_string_lossy().into_owned();
+
+    assert_eq!(io("create_dir")(&[path("")]), Ok(Object::Void));
+    assert_eq!(io("write_file")(&[path("notes.txt"), string("a\r\nb")]), Ok(Object::Void));
+    assert_eq!(io("read_lines")(&[path("notes.txt")]), Ok(Object::Array(vec!(string("a"), string("b")))));
+    assert!(matches!(io("list_dir")(&[path("notes.txt")]), Err(message) if message.starts_with(&format!("Could not list [{}]: ", file))));
+    assert!(matches!(io("read_file")(&[path("missing")]), Err(message) if message.starts_with("Could not read [")));
+    assert!(matches!(io("write_file")(&[path("missing/notes.txt"), string("")]), Err(message) if message.starts_with("Could not write [")));
+    assert_eq!((io("exists")(&[path("missing")]), io("is_dir")(&[path("")])), (Ok(Object::Boolean(false)), Ok(Object::Boolean(true))));
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn seeded_numbers_repeat_and_stay_in_range() {
+    let draw = |seed| {
+      let mut rng = Rng::new(seed);
+      (0..100).map(|_| rng.below(6)).collect::<Vec<u64>>()
+    };
+    assert_eq!(draw(42), draw(42));
+    assert_ne!(draw(42), draw(43));
+    assert!(draw(7).iter().all(|n| *n < 6));
+    assert!((0..6).all(|n| draw(7).contains(&n)));
+
+    let mut rng = Rng::new(1);
+    assert!((0..1000).map(|_| rng.unit()).all(|x| (0.0..1.0).contains(&x)));
+  }
+
+  #[test]
+  fn displays_nested_values() {
+    let mut env = Env::new();
+    env.set_var("name".to_string(), Object::Variable(Variable{ name: "name".to_string(), ty: Type::String, value: Box::new(string("Ada")) }));
+    env.set_var("tags".to_string(), Object::Variable(Variable{ name: "tags".to_string(), ty: Type::Any,
+      value: Box::new(Object::Hash(vec!((string("a"), Object::Array(vec!(Object::Integer(Integer{ value: 1 }), Object::Null)))))) }));
+    let instance = Object::Instance(Instance{ class: "User".to_string(), fields: vec!("name".to_string(), "tags".to_string()), env });
+
+    assert_eq!(Object::Array(vec!(instance, Object::Decimal(Decimal{ value: 2.0 }), Object::Boolean(true))).to_string(),
+      "[User(name: \"Ada\", tags: {\"a\": [1, null]}), 2.0, true]");
+    assert_eq!(string("bare").to_string(), "bare");
+  }
+
+  #[test]
+  fn formats_placeholders() {
+    let args = [string("ratio"), Object::Decimal(Decimal{ value: 2.0 / 3.0 }), Object::Integer(Integer{ value: 7 })];
+    assert_eq!(format("{} is {:.2}, {{{}}}", &args), Ok("ratio is 0.67, {7}".to_string()));
+    assert_eq!(format("{} and {}", &args[..1]), Err("[format] has more placeholders than values in [{} and {}]".to_string()));
+    assert_eq!(format("{:x}", &args[..1]), Err("Unknown format [{:x}], expected {} or {:.N}".to_string()));
+  }
+}
@@ -1,175 +1,1111 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+use cst::{GreenBuilder, NodeKind, SyntaxNode};
+use diagnostic::Diagnostic;
+use interner::Symbol;
+use lexer::LexError;
+use source_map::{FileId, Span};
 use structs::structs::*;
 
-pub struct Parser {
+// A newline right after one of these does not end the statement, the expression goes on.
+const CONTINUES: [TokenType; 28] = [
+  TokenType::Plus, TokenType::Minus, TokenType::Star, TokenType::Slash, TokenType::StarStar, TokenType::Percent,
+  TokenType::EqualsEquals, TokenType::NotEqual, TokenType::LessThan, TokenType::GreaterThan, TokenType::LessThanOrEqual,
+  TokenType::GreaterOrEqual, TokenType::And, TokenType::Or, TokenType::Elvis, TokenType::Equals, TokenType::PlusEqual,
+  TokenType::MinusEqual, TokenType::StarEqual, TokenType::SlashEqual, TokenType::PercentEqual, TokenType::FatArrow, TokenType::Dot, TokenType::QuestionDot, TokenType::Comma, TokenType::LPar,
+  TokenType::LBrac, TokenType::Keyword(Keyword::As)
+];
+
+// Keywords that name a builtin type.
+const TYPE_KEYWORDS: [Keyword; 8] = [Keyword::Integer, Keyword::Decimal, Keyword::BigInteger, Keyword::Boolean, Keyword::String,
+  Keyword::Array, Keyword::Hash, Keyword::Void];
+
+// Reads tokens into the lossless tree, `cst::SourceFile` lowers that to the AST. The first mistake ends
+// the parse with a Diagnostic pointing at it.
+pub struct Parser<'a> {
   pub pos: usize,
-  pub last_pos: usize,
   pub current_token: Option<Token>,
   pub next_token: Option<Token>,
-  pub tokens: Vec<Token>,
-  pub source: String
+  // Tokens pulled from `input` and not yet left behind, from the current one to the furthest lookahead.
+  pub tokens: VecDeque<Token>,
+  // Index in the whole token stream of the front of `tokens`.
+  pub offset: usize,
+  // Comments not yet in `cst`, kept out of `tokens` so the grammar never has to skip them.
+  pub trivia: VecDeque<Token>,
+  pub input: Box<dyn Iterator<Item = Result<Token, LexError>> + 'a>,
+  // Where the input stopped lexing. The tokens end there, so whatever the grammar trips over once
+  // they run out, this is what went wrong.
+  pub lex_error: Option<Diagnostic>,
+  pub cst: GreenBuilder<'a>,
+  pub file: FileId,
+  pub source: &'a str,
+  // End of the last consumed token, where the node being parsed ends so far.
+  pub last_end: usize
 }
 
-impl Parser {
+impl<'a> Parser<'a> {
+  pub fn new(input: Box<dyn Iterator<Item = Result<Token, LexError>> + 'a>, source: &'a str, file: FileId) -> Parser<'a> {
+    Parser{ pos: 0, current_token: None, next_token: None, tokens: VecDeque::new(), offset: 0, trivia: VecDeque::new(), input,
+      lex_error: None, cst: GreenBuilder::new(source), file, source, last_end: 0 }
+  }
+
   fn next(&mut self) -> Option<Token> {
-    if self.pos > self.last_pos {
-        // Means we reached the end.
-        // Set everything to None.
-        self.current_token = None;
-        self.next_token = None;
-        return None;
+    let continues = self.current_token.as_ref().is_some_and(|token| CONTINUES.contains(&token.ty));
+    self.advance(continues)
+  }
+
+  // Moves past the current token, and past the newlines after it when the statement goes on.
+  fn advance(&mut self, continues: bool) -> Option<Token> {
+    if let Some(token) = self.current_token.take() {
+      self.last_end = token.range.end;
+      self.cst_token(&token);
+    }
+    self.current_token = self.token_at(self.pos);
+    self.next_token = self.token_at(self.pos + 1);
+    if self.current_token.is_some() {
+      self.pos += 1;
+    }
+    // Lookahead never goes back past the current token.
+    while self.offset + 1 < self.pos {
+      self.tokens.pop_front();
+      self.offset += 1;
     }
-    self.current_token = Some(self.tokens[self.pos].clone()); // Update current_token
+    if continues {
+      self.skip_newlines();
+    }
+    self.next_token.clone() // Return the next token.
+  }
 
-    if self.pos + 1 > self.last_pos {
-        // Means we are on the last token.
-        // Set next_token to None.
-        self.next_token = None;
-        self.pos += 1;
+  // Pulls from the input until `pos` is buffered, None past the end or past a lexer error.
+  fn token_at(&mut self, pos: usize) -> Option<Token> {
+    while self.offset + self.tokens.len() <= pos {
+      if self.lex_error.is_some() {
         return None;
+      }
+      match self.input.next()? {
+        Ok(token) if matches!(token.ty, TokenType::Comment(_)) => self.trivia.push_back(token),
+        Ok(token) => self.tokens.push_back(token),
+        Err(error) => self.lex_error = Some(Diagnostic::error(error.message, Span{ file: self.file, range: error.range }))
+      }
     }
-    self.next_token = Some(self.tokens[self.pos + 1].clone()); // Update next_token
-    self.pos += 1; // Update our position
+    Some(self.tokens[pos - self.offset].clone())
+  }
 
-    return Some(self.tokens[self.pos].clone()); // Return the next token.
+  // Comments and whitespace before `end` go into the tree first, all comments when there is no end.
+  fn cst_trivia(&mut self, end: Option<usize>) {
+    while self.trivia.front().is_some_and(|comment| end.is_none_or(|end| comment.range.start < end)) {
+      let comment = self.trivia.pop_front().unwrap();
+      self.cst.token(&comment);
+    }
+    if let Some(end) = end {
+      self.cst.whitespace(end);
+    }
   }
-  
-  fn expect(&mut self, ty: Vec<TokenType>) {
-    match self.current_token.clone().unwrap() {
-      token if ty.contains(&token.ty) => { self.next(); },
-      _ => panic!("Expected [{:?}], instead found [{:?}]", ty, self.current_token.clone().unwrap().ty)
+
+  fn cst_token(&mut self, token: &Token) {
+    self.cst_trivia(Some(token.range.start));
+    self.cst.token(token);
+  }
+
+  // Opens a node at the current token, comments in front of it stay outside.
+  fn start_node(&mut self, kind: NodeKind) {
+    let start = self.current_token.clone().map(|token| token.range.start);
+    self.cst_trivia(start);
+    self.cst.start_node(kind);
+  }
+
+  fn checkpoint(&mut self) -> usize {
+    let start = self.current_token.clone().map(|token| token.range.start);
+    self.cst_trivia(start);
+    self.cst.checkpoint()
+  }
+
+  fn finish_node(&mut self) {
+    self.cst.finish_node();
+  }
+
+  // Where the node starting at the current token begins.
+  fn start(&self) -> usize {
+    self.current_token.as_ref().map(|token| token.range.start).unwrap_or(self.last_end)
+  }
+
+  // An error pointing into the source. Once the tokens have run out at a lexer error, that error is the one to report.
+  fn error(&mut self, message: String, range: Range<usize>) -> Diagnostic {
+    match self.lex_error.take() {
+      Some(error) if self.current_token.is_none() => error,
+      lex_error => {
+        self.lex_error = lex_error;
+        Diagnostic::error(message, Span{ file: self.file, range })
+      }
     }
   }
-  
+
+  // The current token was not what the grammar wanted.
+  fn unexpected(&mut self, message: String) -> Diagnostic {
+    let range = self.current_token.as_ref().map_or(self.last_end..self.last_end, |token| token.range.clone());
+    self.error(message, range)
+  }
+
+  // The token being looked at, the input running out here is an error.
+  fn current(&mut self) -> Result<Token, Diagnostic> {
+    match &self.current_token {
+      Some(token) => Ok(token.clone()),
+      None => Err(self.unexpected("Unexpected end of input".to_string()))
+    }
+  }
+
+  fn expect(&mut self, ty: Vec<TokenType>) -> Result<(), Diagnostic> {
+    match self.current_token.clone() {
+      Some(token) if ty.contains(&token.ty) => { self.next(); Ok(()) },
+      Some(token) => Err(self.unexpected(format!("Expected [{:?}], instead found [{:?}]", ty, token.ty))),
+      None => Err(self.unexpected(format!("Unexpected end of input, expected [{:?}]", ty)))
+    }
+  }
+
   fn skip_newlines(&mut self) {
-    while self.current_token != None && self.current_token.clone().unwrap().ty == TokenType::Newline {
+    while self.current_token.is_some() && self.current_token.clone().unwrap().ty == TokenType::Newline {
       self.next();
     }
   }
 
-  pub fn parse(&mut self) -> Vec<AST> {
-    let mut program: Vec<AST> = Vec::new();
-    self.next();
-    self.skip_newlines();
+  fn skip_terminators(&mut self) {
+    while self.current_token.is_some() && [TokenType::Newline, TokenType::Semi].contains(&self.current_token.clone().unwrap().ty) {
+      self.next();
+    }
+  }
+
+  // A statement ends at a newline or `;`, or where its block or the file ends.
+  fn end_statement(&mut self) -> Result<(), Diagnostic> {
+    match self.current_token.clone() {
+      None => {},
+      Some(token) if token.ty == TokenType::RCurl => {},
+      Some(token) if [TokenType::Newline, TokenType::Semi].contains(&token.ty) => self.skip_terminators(),
+      Some(token) => return Err(self.unexpected(format!("Expected a newline or [;] after the statement, instead found [{:?}]", token.ty)))
+    }
+    Ok(())
+  }
 
-    while self.current_token != None {
-      program.push(self.parse_top());
+  fn skip_separators(&mut self) {
+    while self.current_token.is_some() && [TokenType::Newline, TokenType::Semi, TokenType::Comma].contains(&self.current_token.clone().unwrap().ty) {
       self.next();
-      self.skip_newlines();
     }
-    return program
   }
 
-  fn parse_top(&mut self) -> AST {
-    let current = self.current_token.clone().unwrap();
+  fn current_is(&self, ty: TokenType) -> bool {
+    self.current_token.is_some() && self.current_token.clone().unwrap().ty == ty
+  }
+
+  fn current_symbol(&mut self) -> Result<Symbol, Diagnostic> {
+    let current = self.current()?;
+    match current.value {
+      TokenValue::Symbol(symbol) => Ok(symbol),
+      _ => Err(self.unexpected(format!("Expected a name, instead found [{:?}]", current.ty)))
+    }
+  }
+
+  fn current_text(&mut self) -> Result<String, Diagnostic> {
+    Ok(self.current_symbol()?.to_string())
+  }
+
+  // The tree of the whole program, or the first mistake in it.
+  pub fn parse(&mut self) -> Result<SyntaxNode, Diagnostic> {
+    self.parse_program()?;
+    match self.lex_error.take() {
+      Some(error) => Err(error),
+      None => Ok(self.cst.finish())
+    }
+  }
+
+  fn parse_program(&mut self) -> Result<(), Diagnostic> {
+    self.cst.start_node(NodeKind::SourceFile);
+    self.next();
+    self.skip_terminators();
+
+    while self.current_token.is_some() {
+      self.parse_top()?;
+      self.end_statement()?;
+    }
+    self.cst_trivia(None);
+    self.cst.rest();
+    self.finish_node();
+    Ok(())
+  }
+
+  fn parse_top(&mut self) -> Result<(), Diagnostic> {
+    let current = self.current()?;
 
     if let TokenType::Keyword(kw) = &current.ty {
-      if kw == &Keyword::Integer || kw == &Keyword::Decimal || kw == &Keyword::Boolean {
-        self.parse_assignment(kw.clone())
+      if TYPE_KEYWORDS.contains(kw) {
+        self.parse_assignment(false)
+      } else if kw == &Keyword::Funk && self.next_token.is_some() && self.next_token.clone().unwrap().ty == TokenType::LPar {
+        // `funk(Integer) Integer f = ...`, a lambda on its own would do nothing.
+        self.parse_assignment(false)
+      } else if kw == &Keyword::Mut {
+        self.parse_mut()
       } else if kw == &Keyword::Funk {
         self.parse_function()
+      } else if kw == &Keyword::Enum {
+        self.parse_enum()
+      } else if kw == &Keyword::When {
+        self.parse_when()
+      } else if kw == &Keyword::Klass {
+        self.parse_klass()
+      } else if kw == &Keyword::Interface {
+        self.parse_interface()
+      } else if kw == &Keyword::Return {
+        self.parse_return()
+      } else if kw == &Keyword::Null {
+        self.parse_expr()
+      } else if kw == &Keyword::Let {
+        self.parse_let(false)
+      } else if kw == &Keyword::Try {
+        self.parse_try()
+      } else if kw == &Keyword::Throw {
+        self.parse_throw()
       }
 
       else {
-        panic!("Keywords not handled yet")
+        Err(self.unexpected(format!("Keyword [{:?}] is not handled yet", kw)))
       }
+    } else if current.ty == TokenType::Identifier && self.at_declaration() {
+      // `Shape s = ...`, `Shape? s = ...` or `Array<String> names = ...`.
+      self.parse_assignment(false)
     } else {
-      self.parse_expr()
+      self.parse_expression_statement()
+    }
+  }
+
+  // An expression, or an assignment to a variable or field: `x = 1`, `p.x += 2`.
+  fn parse_expression_statement(&mut self) -> Result<(), Diagnostic> {
+    let start = self.start();
+    let checkpoint = self.checkpoint();
+    self.parse_expr()?;
+
+    let assigns = self.current_token.as_ref().is_some_and(|token| token.ty == TokenType::Equals || BinOp::compound(&token.ty).is_some());
+    if ! assigns {
+      return Ok(());
+    }
+    self.expect_place(start)?;
+    self.cst.start_node_at(checkpoint, NodeKind::Reassign);
+    self.next();
+    self.parse_expr()?;
+    self.finish_node();
+    Ok(())
+  }
+
+  // The expression just parsed, from `start`, has to name a variable, a field or an Array element.
+  fn expect_place(&mut self, start: usize) -> Result<(), Diagnostic> {
+    if ! self.cst.last_node().is_some_and(|node| node.is_place()) {
+      let message = format!("Cannot assign to [{}], only to variables, fields and Array elements", &self.source[start..self.last_end]);
+      return Err(self.error(message, start..self.last_end));
+    }
+    Ok(())
+  }
+
+  // Whether a type and then a name start at the current token. Only looks ahead, nothing is consumed.
+  fn at_declaration(&mut self) -> bool {
+    match self.skip_type(self.pos - 1) {
+      Some(end) => self.token_at(end).is_some_and(|token| token.ty == TokenType::Identifier),
+      None => false
+    }
+  }
+
+  // Index of the token after the type starting at `pos`, None when no type starts there.
+  fn skip_type(&mut self, pos: usize) -> Option<usize> {
+    let mut pos = match self.token_at(pos)?.ty {
+      TokenType::Identifier => pos + 1,
+      TokenType::Keyword(kw) if TYPE_KEYWORDS.contains(&kw) => pos + 1,
+      TokenType::Keyword(Keyword::Funk) if self.token_at(pos + 1)?.ty == TokenType::LPar => {
+        let mut pos = self.skip_newlines_at(pos + 2);
+        while self.token_at(pos)?.ty != TokenType::RPar {
+          pos = self.skip_type(pos)?;
+          if self.token_at(pos)?.ty == TokenType::Comma {
+            pos = self.skip_newlines_at(pos + 1);
+          }
+        }
+        return self.skip_type(pos + 1);
+      },
+      _ => return None
+    };
+
+    if self.token_at(pos).is_some_and(|token| token.ty == TokenType::LessThan) {
+      loop {
+        let arg = self.skip_newlines_at(pos + 1);
+        pos = self.skip_type(arg)?;
+        if self.token_at(pos)?.ty != TokenType::Comma {
+          break;
+        }
+      }
+      if self.token_at(pos)?.ty != TokenType::GreaterThan {
+        return None;
+      }
+      pos += 1;
+    }
+    if self.token_at(pos).is_some_and(|token| token.ty == TokenType::Question) {
+      pos += 1;
+    }
+    Some(pos)
+  }
+
+  fn skip_newlines_at(&mut self, mut pos: usize) -> usize {
+    while self.token_at(pos).is_some_and(|token| token.ty == TokenType::Newline) {
+      pos += 1;
+    }
+    pos
+  }
+
+  // Every type in the source goes through here: declarations, parameters, fields, return types and casts.
+  fn parse_type(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::Type);
+    self.parse_type_name()?;
+    self.finish_node();
+    Ok(())
+  }
+
+  fn parse_type_name(&mut self) -> Result<(), Diagnostic> {
+    let current = self.current()?;
+    match &current.ty {
+      TokenType::Keyword(Keyword::Funk) => return self.parse_function_type(),
+      TokenType::Keyword(kw) if TYPE_KEYWORDS.contains(kw) => {},
+      TokenType::Identifier => {},
+      _ => return Err(self.unexpected(format!("Expected a type, instead found [{:?}]", current.ty)))
+    }
+    self.expect(vec!(current.ty))?;
+
+    // Array<Integer>, Hash<String, Integer>
+    if self.current_is(TokenType::LessThan) {
+      self.expect(vec!(TokenType::LessThan))?;
+      self.parse_type()?;
+      while self.current_is(TokenType::Comma) {
+        self.expect(vec!(TokenType::Comma))?;
+        self.parse_type()?;
+      }
+      // Closes the type rather than comparing, a newline after it still ends the statement.
+      if self.current_is(TokenType::GreaterThan) {
+        self.advance(false);
+      } else {
+        self.expect(vec!(TokenType::GreaterThan))?;
+      }
+    }
+
+    if self.current_is(TokenType::Question) {
+      self.expect(vec!(TokenType::Question))?;
+    }
+    Ok(())
+  }
+
+  // funk(Integer, Decimal) Integer
+  fn parse_function_type(&mut self) -> Result<(), Diagnostic> {
+    self.expect(vec!(TokenType::Keyword(Keyword::Funk)))?;
+    self.expect(vec!(TokenType::LPar))?;
+
+    while ! self.current_is(TokenType::RPar) {
+      self.parse_type()?;
+      if ! self.current_is(TokenType::RPar) {
+        self.expect(vec!(TokenType::Comma))?;
+      }
+    }
+    self.expect(vec!(TokenType::RPar))?;
+    self.parse_type()
+  }
+
+  fn parse_block(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::Block);
+    self.expect(vec!(TokenType::LCurl))?;
+    self.skip_terminators();
+
+    while self.current_token.is_some() && ! self.current_is(TokenType::RCurl) {
+      self.parse_top()?;
+      self.end_statement()?;
+    }
+    self.expect(vec!(TokenType::RCurl))?;
+    self.finish_node();
+    Ok(())
+  }
+
+  fn parse_enum(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::Enum);
+    self.expect(vec!(TokenType::Keyword(Keyword::Enum)))?;
+
+    let enum_name = self.current_text()?;
+    self.expect(vec!(TokenType::Identifier))?;
+    self.expect(vec!(TokenType::LCurl))?;
+    self.skip_separators();
+
+    let mut variants: Vec<String> = vec!();
+    while ! self.current_is(TokenType::RCurl) {
+      self.start_node(NodeKind::Variant);
+      let variant_name = self.current_text()?;
+      if variants.contains(&variant_name) {
+        return Err(self.unexpected(format!("Variant [{}] is declared twice in enum [{}]", variant_name, enum_name)));
+      }
+      self.expect(vec!(TokenType::Identifier))?;
+
+      if self.current_is(TokenType::LPar) {
+        self.expect(vec!(TokenType::LPar))?;
+        while ! self.current_is(TokenType::RPar) {
+          self.parse_type()?;
+          if ! self.current_is(TokenType::RPar) {
+            self.expect(vec!(TokenType::Comma))?;
+          }
+        }
+        self.expect(vec!(TokenType::RPar))?;
+      }
+
+      self.finish_node();
+      variants.push(variant_name);
+      self.skip_separators();
+    }
+    self.expect(vec!(TokenType::RCurl))?;
+    self.finish_node();
+    Ok(())
+  }
+
+  fn parse_when(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::When);
+    self.expect(vec!(TokenType::Keyword(Keyword::When)))?;
+    self.expect(vec!(TokenType::LPar))?;
+    self.parse_expr()?;
+    self.expect(vec!(TokenType::RPar))?;
+    self.expect(vec!(TokenType::LCurl))?;
+    self.skip_separators();
+
+    while ! self.current_is(TokenType::RCurl) {
+      self.start_node(NodeKind::WhenArm);
+      self.parse_pattern()?;
+      self.expect(vec!(TokenType::FatArrow))?;
+
+      if self.current_is(TokenType::LCurl) {
+        self.parse_block()?;
+      } else {
+        self.parse_top()?;
+      }
+      self.finish_node();
+      self.skip_separators();
+    }
+    self.expect(vec!(TokenType::RCurl))?;
+    self.finish_node();
+    Ok(())
+  }
+
+  fn parse_pattern(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::Pattern);
+    self.parse_pattern_inner()?;
+    self.finish_node();
+    Ok(())
+  }
+
+  fn parse_pattern_inner(&mut self) -> Result<(), Diagnostic> {
+    if self.current_symbol()? == Symbol::intern("_") {
+      return self.expect(vec!(TokenType::Identifier));
+    }
+
+    self.expect(vec!(TokenType::Identifier))?;
+    self.expect(vec!(TokenType::ColonColon))?;
+    self.expect(vec!(TokenType::Identifier))?;
+
+    if self.current_is(TokenType::LPar) {
+      self.expect(vec!(TokenType::LPar))?;
+      while ! self.current_is(TokenType::RPar) {
+        self.expect(vec!(TokenType::Identifier))?;
+        if ! self.current_is(TokenType::RPar) {
+          self.expect(vec!(TokenType::Comma))?;
+        }
+      }
+      self.expect(vec!(TokenType::RPar))?;
     }
+    Ok(())
   }
 
-  fn parse_function(&mut self) -> AST {
-    self.expect(vec!(self.current_token.clone().unwrap().ty));
+  fn parse_function(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::Function);
+    self.parse_signature()?;
+    self.parse_block()?;
+    self.finish_node();
+    Ok(())
+  }
 
-    let func_name = self.source[self.current_token.clone().unwrap().range].to_string();
-    self.expect(vec!(TokenType::Identifier));
-    let func_params = self.parse_params();
+  // `funk name(Type a, Type b) ReturnType`, shared by functions and interface methods.
+  fn parse_signature(&mut self) -> Result<(), Diagnostic> {
+    self.expect(vec!(TokenType::Keyword(Keyword::Funk)))?;
+    self.expect(vec!(TokenType::Identifier))?;
+    self.parse_params()?;
 
-    // placeholder
-    AST::Expression(Expr::Integer(5))
+    // Without a return type the function returns Void.
+    let ends = [TokenType::LCurl, TokenType::RCurl, TokenType::Newline, TokenType::Semi];
+    if self.current_token.is_some() && ! ends.contains(&self.current_token.clone().unwrap().ty) {
+      self.parse_type()?;
+    }
+    Ok(())
   }
 
-  fn parse_params(&mut self) -> Vec<AST> {
-    self.expect(vec!(TokenType::LPar));
-    let mut params = vec!();
+  fn parse_params(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::ParamList);
+    self.expect(vec!(TokenType::LPar))?;
+    let mut first = true;
 
-    while self.current_token != None {
-      let curr = self.current_token.clone().unwrap();
+    loop {
+      let curr = self.current()?;
       if curr.ty == TokenType::RPar {
-        self.expect(vec!(TokenType::RPar));
+        self.expect(vec!(TokenType::RPar))?;
         break;
       }
 
       if curr.ty == TokenType::Comma {
-        if self.next_token != None && ! matches!(self.next_token.clone().unwrap().ty, TokenType::Keyword(_)) {
-          panic!("")
+        if first {
+          return Err(self.unexpected("Expected a parameter before [,]".to_string()));
         }
-        self.expect(vec!(TokenType::Comma));
+        self.expect(vec!(TokenType::Comma))?;
       }
 
-      if let TokenType::Keyword(kw) = curr.ty {
-        let arg_type = kw;
-        self.expect(vec!(self.current_token.clone().unwrap().ty));
+      // A bare name leaves the type to the checker, which only lambdas allow.
+      self.start_node(NodeKind::Param);
+      let untyped = self.current_is(TokenType::Identifier) && self.next_token.is_some()
+        && [TokenType::Comma, TokenType::RPar].contains(&self.next_token.clone().unwrap().ty);
+      if ! untyped {
+        self.parse_type()?;
+      }
+      self.expect(vec!(TokenType::Identifier))?;
 
-        let arg_name = self.source[curr.range].to_string();
-        self.expect(vec!(TokenType::Identifier));
+      self.finish_node();
+      first = false;
+      self.skip_newlines();
+    }
+    self.finish_node();
+    Ok(())
+  }
+
+  // funk(x, Integer y) Integer { ... }, the return type may be left to the checker.
+  fn parse_lambda(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::Lambda);
+    self.expect(vec!(TokenType::Keyword(Keyword::Funk)))?;
+    self.parse_params()?;
+    if ! self.current_is(TokenType::LCurl) {
+      self.parse_type()?;
+    }
+    self.parse_block()?;
+    self.finish_node();
+    Ok(())
+  }
 
-        params.push(AST::Expression(Expr::Argument{name: arg_name, ty: arg_type}));
+  fn parse_klass(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::Klass);
+    self.expect(vec!(TokenType::Keyword(Keyword::Klass)))?;
+    self.expect(vec!(TokenType::Identifier))?;
+
+    if self.current_is(TokenType::Keyword(Keyword::Implements)) {
+      self.expect(vec!(TokenType::Keyword(Keyword::Implements)))?;
+      loop {
+        self.expect(vec!(TokenType::Identifier))?;
+        if ! self.current_is(TokenType::Comma) {
+          break;
+        }
+        self.expect(vec!(TokenType::Comma))?;
       }
     }
-    params
+
+    self.expect(vec!(TokenType::LCurl))?;
+    self.skip_separators();
+
+    while ! self.current_is(TokenType::RCurl) {
+      // `funk(Integer) Integer f` is a field holding a function.
+      if self.current_is(TokenType::Keyword(Keyword::Funk)) && ! self.next_token.clone().is_some_and(|token| token.ty == TokenType::LPar) {
+        self.parse_function()?;
+      } else {
+        self.start_node(NodeKind::Field);
+        if self.current_is(TokenType::Keyword(Keyword::Mut)) {
+          self.expect(vec!(TokenType::Keyword(Keyword::Mut)))?;
+        }
+        self.parse_type()?;
+        self.expect(vec!(TokenType::Identifier))?;
+        self.finish_node();
+      }
+      self.skip_separators();
+    }
+    self.expect(vec!(TokenType::RCurl))?;
+    self.finish_node();
+    Ok(())
+  }
+
+  fn parse_interface(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::Interface);
+    self.expect(vec!(TokenType::Keyword(Keyword::Interface)))?;
+    self.expect(vec!(TokenType::Identifier))?;
+    self.expect(vec!(TokenType::LCurl))?;
+    self.skip_separators();
+
+    while ! self.current_is(TokenType::RCurl) {
+      self.start_node(NodeKind::MethodSignature);
+      self.parse_signature()?;
+      self.finish_node();
+      self.skip_separators();
+    }
+    self.expect(vec!(TokenType::RCurl))?;
+    self.finish_node();
+    Ok(())
   }
 
-  fn parse_assignment(&mut self, kw: Keyword) -> AST {
-    let var_type = match kw {
-      Keyword::Integer => Type::Integer,
-      Keyword::Decimal => Type::Decimal,
-      _ => panic!("")
+  fn parse_return(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::Return);
+    self.expect(vec!(TokenType::Keyword(Keyword::Return)))?;
+
+    let ends = [TokenType::RCurl, TokenType::Newline, TokenType::Semi];
+    if self.current_token.is_some() && ! ends.contains(&self.current_token.clone().unwrap().ty) {
+      self.parse_expr()?;
+    }
+    self.finish_node();
+    Ok(())
+  }
+
+  // try { ... } catch (Error e) { ... } finally { ... }, with any number of catches.
+  fn parse_try(&mut self) -> Result<(), Diagnostic> {
+    let start = self.start();
+    self.start_node(NodeKind::Try);
+    self.expect(vec!(TokenType::Keyword(Keyword::Try)))?;
+    self.parse_block()?;
+
+    let mut clauses = 0;
+    while self.clause_follows(Keyword::Catch) {
+      self.start_node(NodeKind::Catch);
+      self.expect(vec!(TokenType::Keyword(Keyword::Catch)))?;
+      self.expect(vec!(TokenType::LPar))?;
+      self.parse_type()?;
+      self.expect(vec!(TokenType::Identifier))?;
+      self.expect(vec!(TokenType::RPar))?;
+      self.parse_block()?;
+      self.finish_node();
+      clauses += 1;
+    }
+
+    if self.clause_follows(Keyword::Finally) {
+      self.start_node(NodeKind::Finally);
+      self.expect(vec!(TokenType::Keyword(Keyword::Finally)))?;
+      self.parse_block()?;
+      self.finish_node();
+      clauses += 1;
+    }
+    self.finish_node();
+
+    if clauses == 0 {
+      return Err(self.error("`try` needs a catch or a finally".to_string(), start..self.last_end));
+    }
+    Ok(())
+  }
+
+  // Whether `catch` or `finally` comes next, on this line or a following one.
+  fn clause_follows(&mut self, keyword: Keyword) -> bool {
+    let pos = self.skip_newlines_at(self.pos - 1);
+    if self.token_at(pos).is_some_and(|token| token.ty == TokenType::Keyword(keyword)) {
+      self.skip_newlines();
+      return true;
+    }
+    false
+  }
+
+  fn parse_throw(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::Throw);
+    self.expect(vec!(TokenType::Keyword(Keyword::Throw)))?;
+    self.parse_expr()?;
+    self.finish_node();
+    Ok(())
+  }
+
+  // `mut Integer x = 1`, or `mut x = 1` leaving the type to the checker like `let`.
+  fn parse_mut(&mut self) -> Result<(), Diagnostic> {
+    let typed = match self.skip_type(self.pos) {
+      Some(end) => self.token_at(end).is_some_and(|token| token.ty == TokenType::Identifier),
+      None => false
     };
-    self.expect(vec!(self.current_token.clone().unwrap().ty));
-    
-    let var_name = self.source[self.current_token.clone().unwrap().range].to_string();
-    self.expect(vec!(self.current_token.clone().unwrap().ty));
-    self.expect(vec!(self.current_token.clone().unwrap().ty));
+    if typed { self.parse_assignment(true) } else { self.parse_let(true) }
+  }
+
+  fn parse_let(&mut self, mutable: bool) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::Let);
+    self.expect(vec!(TokenType::Keyword(if mutable { Keyword::Mut } else { Keyword::Let })))?;
+    self.expect(vec!(TokenType::Identifier))?;
+    self.expect(vec!(TokenType::Equals))?;
+    self.parse_expr()?;
+    self.finish_node();
+    Ok(())
+  }
+
+  fn parse_assignment(&mut self, mutable: bool) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::Assignment);
+    if mutable {
+      self.expect(vec!(TokenType::Keyword(Keyword::Mut)))?;
+    }
+    self.parse_type()?;
+    self.expect(vec!(TokenType::Identifier))?;
+    self.expect(vec!(TokenType::Equals))?;
+    self.parse_expr()?;
+    self.finish_node();
+    Ok(())
+  }
 
-    let var_val = self.parse_top();
-    AST::Statement(Statement::Assignment(Assign{name: var_name, ty: var_type, value: Box::new(var_val)}))
+  fn parse_expr(&mut self) -> Result<(), Diagnostic> {
+    let checkpoint = self.checkpoint();
+    self.parse_sum()?;
+
+    while self.current_is(TokenType::Elvis) {
+      self.cst.start_node_at(checkpoint, NodeKind::ElvisExpr);
+      self.expect(vec!(TokenType::Elvis))?;
+      self.parse_sum()?;
+      self.finish_node();
+    }
+    Ok(())
   }
 
-  fn parse_expr(&mut self) -> AST {
-    let mut result = self.parse_term();
+  fn parse_sum(&mut self) -> Result<(), Diagnostic> {
+    let checkpoint = self.checkpoint();
+    self.parse_term()?;
 
-    while self.current_token != None && (self.current_token.clone().unwrap().ty == TokenType::Plus || self.current_token.clone().unwrap().ty == TokenType::Minus) {
-      let op = BinOp::new(&self.current_token.clone().unwrap().ty);
-      self.expect(Vec::from([TokenType::Plus, TokenType::Minus]));
-      result = AST::Expression(Expr::Binary(BinaryExpr { left: Box::new(result), op, right: Box::new(self.parse_expr()) }));
+    while self.current_token.is_some() && (self.current_token.clone().unwrap().ty == TokenType::Plus || self.current_token.clone().unwrap().ty == TokenType::Minus) {
+      self.cst.start_node_at(checkpoint, NodeKind::BinaryExpr);
+      self.expect(Vec::from([TokenType::Plus, TokenType::Minus]))?;
+      self.parse_term()?;
+      self.finish_node();
     }
-    return result
+    Ok(())
   }
 
-  fn parse_term(&mut self) -> AST {
-    let mut result = self.parse_factor();
+  fn parse_term(&mut self) -> Result<(), Diagnostic> {
+    let checkpoint = self.checkpoint();
+    self.parse_factor()?;
 
-    while self.current_token != None && (self.current_token.clone().unwrap().ty == TokenType::Star || self.current_token.clone().unwrap().ty == TokenType::Slash) {
-      let op = BinOp::new(&self.current_token.clone().unwrap().ty);
-      self.expect(Vec::from([TokenType::Star, TokenType::Slash]));
-      result = AST::Expression(Expr::Binary(BinaryExpr { left: Box::new(result), op, right: Box::new(self.parse_expr()) }));
+    while self.current_token.is_some() && [TokenType::Star, TokenType::Slash, TokenType::Percent].contains(&self.current_token.clone().unwrap().ty) {
+      self.cst.start_node_at(checkpoint, NodeKind::BinaryExpr);
+      self.expect(Vec::from([TokenType::Star, TokenType::Slash, TokenType::Percent]))?;
+      self.parse_factor()?;
+      self.finish_node();
     }
-    return result;
+    Ok(())
   }
 
-  fn parse_factor(&mut self) -> AST {
-    let current = self.current_token.clone().unwrap();
-  
-    if TokenType::Literal(Val::Number) == current.ty {
-      let num_str = self.source[current.range].to_string();
-      self.expect(Vec::from([TokenType::Literal(Val::Number)]));
-      if num_str.contains('.') {
-        AST::Expression(Expr::Decimal(num_str.parse::<f64>().unwrap()))
+  fn parse_factor(&mut self) -> Result<(), Diagnostic> {
+    let start = self.start();
+    let checkpoint = self.checkpoint();
+    self.parse_primary()?;
+
+    while self.current_is(TokenType::Dot) || self.current_is(TokenType::QuestionDot) || self.current_is(TokenType::LBrac) {
+      if self.current_is(TokenType::LBrac) {
+        self.cst.start_node_at(checkpoint, NodeKind::IndexExpr);
+        self.expect(vec!(TokenType::LBrac))?;
+        self.parse_expr()?;
+        self.skip_newlines();
+        self.expect(vec!(TokenType::RBrac))?;
+        self.finish_node();
+        continue;
+      }
+      self.expect(vec!(TokenType::Dot, TokenType::QuestionDot))?;
+      self.expect(vec!(TokenType::Identifier))?;
+
+      if self.current_is(TokenType::LPar) {
+        self.parse_args()?;
+        self.cst.start_node_at(checkpoint, NodeKind::MethodCallExpr);
       } else {
-        AST::Expression(Expr::Integer(num_str.parse::<i64>().unwrap()))
+        self.cst.start_node_at(checkpoint, NodeKind::FieldExpr);
+      }
+      self.finish_node();
+    }
+
+    if self.current_is(TokenType::PlusPlus) || self.current_is(TokenType::MinusMinus) {
+      self.expect_place(start)?;
+      self.cst.start_node_at(checkpoint, NodeKind::IncrementExpr);
+      self.expect(vec!(TokenType::PlusPlus, TokenType::MinusMinus))?;
+      self.finish_node();
+    }
+
+    while self.current_is(TokenType::Keyword(Keyword::As)) {
+      self.cst.start_node_at(checkpoint, NodeKind::CastExpr);
+      self.expect(vec!(TokenType::Keyword(Keyword::As)))?;
+      self.parse_type()?;
+      self.finish_node();
+    }
+    Ok(())
+  }
+
+  fn parse_args(&mut self) -> Result<(), Diagnostic> {
+    self.start_node(NodeKind::ArgList);
+    self.expect(vec!(TokenType::LPar))?;
+    while ! self.current_is(TokenType::RPar) {
+      self.parse_expr()?;
+      self.skip_newlines();
+      if ! self.current_is(TokenType::RPar) {
+        self.expect(vec!(TokenType::Comma))?;
       }
+    }
+    self.expect(vec!(TokenType::RPar))?;
+    self.finish_node();
+    Ok(())
+  }
+
+  fn parse_primary(&mut self) -> Result<(), Diagnostic> {
+    let current = self.current()?;
+
+    if [TokenType::Literal(Val::Number), TokenType::Literal(Val::String), TokenType::Keyword(Keyword::Null)].contains(&current.ty) {
+      self.start_node(NodeKind::Literal);
+      self.expect(vec!(current.ty))?;
+      self.finish_node();
+    } else if TokenType::Identifier == current.ty {
+      let checkpoint = self.checkpoint();
+      self.expect(vec!(TokenType::Identifier))?;
+
+      let kind = if self.current_is(TokenType::ColonColon) {
+        self.parse_variant()?;
+        NodeKind::VariantExpr
+      } else if self.current_is(TokenType::LPar) {
+        self.parse_args()?;
+        NodeKind::CallExpr
+      } else {
+        NodeKind::NameRef
+      };
+      self.cst.start_node_at(checkpoint, kind);
+      self.finish_node();
+    } else if TokenType::Keyword(Keyword::Funk) == current.ty {
+      self.parse_lambda()?;
+    } else if TokenType::Keyword(Keyword::When) == current.ty {
+      self.parse_when()?;
+    } else if TokenType::PlusPlus == current.ty || TokenType::MinusMinus == current.ty {
+      self.start_node(NodeKind::IncrementExpr);
+      self.expect(vec!(current.ty))?;
+      let start = self.start();
+      self.parse_factor()?;
+      self.expect_place(start)?;
+      self.finish_node();
+    } else if TokenType::LPar == current.ty {
+      self.start_node(NodeKind::ParenExpr);
+      self.expect(vec!(TokenType::LPar))?;
+      self.parse_expr()?;
+      self.skip_newlines();
+      self.expect(vec!(TokenType::RPar))?;
+      self.finish_node();
     } else {
-      panic!("")
+      return Err(self.unexpected(format!("Unexpected token [{:?}]", current.ty)));
     }
+    Ok(())
+  }
+
+  // `Shape::Circle(1.0)` or `math::PI`, after the first name.
+  fn parse_variant(&mut self) -> Result<(), Diagnostic> {
+    self.expect(vec!(TokenType::ColonColon))?;
+    self.expect(vec!(TokenType::Identifier))?;
+    if self.current_is(TokenType::LPar) {
+      self.parse_args()?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cst::SourceFile;
+  use lexer::Lexer;
+  use super::*;
+
+  // Parsed and lowered, the way the interpreter gets it.
+  fn parse(code: &str) -> Vec<AST> {
+    SourceFile::parse(code, FileId(0)).and_then(|file| file.to_ast(FileId(0))).unwrap_or_else(|error| panic!("{}", error.message))
+  }
+
+  // What the parser stops at, and where.
+  fn error(code: &str) -> (String, Range<usize>) {
+    let error = Parser::new(Box::new(Lexer::new(code)), code, FileId(0)).parse().unwrap_err();
+    (error.message, error.span.range)
+  }
+
+  fn texts<'a>(code: &'a str, program: &[AST]) -> Vec<&'a str> {
+    program.iter().map(|ast| &code[ast.span().range.clone()]).collect()
+  }
+
+  #[test]
+  fn expressions_continue_past_operators_commas_and_brackets() {
+    let code = "Integer a = 1 +\n  2 *\n  3\n\nInteger b = max(\n  a,\n  4\n)\nlet c = (\n  a ?:\n  b\n)\nInteger d =\n  5\n";
+    let program = parse(code);
+    assert_eq!(texts(code, &program), vec!("Integer a = 1 +\n  2 *\n  3", "Integer b = max(\n  a,\n  4\n)", "let c = (\n  a ?:\n  b\n)",
+      "Integer d =\n  5"));
+  }
+
+  #[test]
+  fn several_statements_on_one_line() {
+    let code = "Integer a = 1; Integer b = 2;; a + b\nfunk f() Integer { Integer x = 1; return x }; f()\n";
+    let program = parse(code);
+    assert_eq!(texts(code, &program), vec!("Integer a = 1", "Integer b = 2", "a + b", "funk f() Integer { Integer x = 1; return x }", "f()"));
+    if let AST::Statement(Statement::Function(f), _) = &program[3] {
+      assert_eq!(f.body.len(), 2);
+    } else {
+      panic!("Expected a function, found {:?}", program[3]);
+    }
+  }
+
+  #[test]
+  fn compound_assignments_keep_the_operator() {
+    let code = "p.x += 2\n--n";
+    let program = parse(code);
+    match &program[0] {
+      AST::Statement(Statement::Reassign{ target, op, value }, _) => {
+        assert_eq!((target.place(), op), (Some("p.x".to_string()), &Some(BinOp::Add)));
+        assert_eq!(&code[value.span().range.clone()], "2");
+      },
+      other => panic!("Expected a reassignment, found {:?}", other)
+    }
+    assert!(matches!(&program[1], AST::Expression(Expr::Increment{ op: BinOp::Subtract, prefix: true, .. }, _)));
+    assert_eq!(texts(code, &program), vec!("p.x += 2", "--n"));
+  }
+
+  #[test]
+  fn indexing_chains_and_assigns() {
+    let code = "grid[i][1 +\n  j] *= 3\np.items[0]++\nlet x = f()[2].y";
+    let program = parse(code);
+    match &program[0] {
+      AST::Statement(Statement::Reassign{ target, .. }, _) => assert_eq!(target.place(), Some("grid[i][..]".to_string())),
+      other => panic!("Expected a reassignment, found {:?}", other)
+    }
+    match &program[1] {
+      AST::Expression(Expr::Increment{ target, prefix: false, .. }, _) => assert_eq!(target.place(), Some("p.items[0]".to_string())),
+      other => panic!("Expected an increment, found {:?}", other)
+    }
+    assert_eq!(texts(code, &program), vec!("grid[i][1 +\n  j] *= 3", "p.items[0]++", "let x = f()[2].y"));
+  }
+
+  #[test]
+  fn only_places_are_assigned() {
+    assert_eq!(error("f()[0] = 1"), ("Cannot assign to [f()[0]], only to variables, fields and Array elements".to_string(), 0..6));
+    assert_eq!(error("Integer a = f()++"), ("Cannot assign to [f()], only to variables, fields and Array elements".to_string(), 12..15));
+  }
+
+  #[test]
+  fn mut_declarations() {
+    let program = parse("mut Array<Integer> xs = ys\nmut n = 1\nInteger fixed = 2\nklass P { mut Integer x; Integer y }");
+    let mutable: Vec<bool> = program[..3].iter().map(|ast| match ast {
+      AST::Statement(Statement::Assignment(assign), _) => assign.mutable,
+      other => panic!("Expected a declaration, found {:?}", other)
+    }).collect();
+    assert_eq!(mutable, vec!(true, true, false));
+    match &program[3] {
+      AST::Statement(Statement::Klass(klass), _) => assert_eq!(klass.mutable, vec!("x")),
+      other => panic!("Expected a klass, found {:?}", other)
+    }
+  }
+
+  #[test]
+  fn type_expressions() {
+    let program = parse("Array<Hash<String, Integer>>? xs = null\nfunk(Array<Integer>, Shape?) Void f = g\nx as Decimal * 2");
+    let types: Vec<Type> = program[..2].iter().map(|ast| match ast {
+      AST::Statement(Statement::Assignment(assign), _) => assign.ty.clone(),
+      other => panic!("Expected a declaration, found {:?}", other)
+    }).collect();
+    assert_eq!(types.iter().map(|ty| ty.to_string()).collect::<Vec<String>>(),
+      vec!("Array<Hash<String, Integer>>?", "funk(Array<Integer>, Shape?) Void"));
+
+    match &program[2] {
+      AST::Expression(Expr::Binary(binary), _) => assert!(matches!(&*binary.left, AST::Expression(Expr::Cast{ ty: Type::Decimal, .. }, _))),
+      other => panic!("Expected a product, found {:?}", other)
+    }
+  }
+
+  #[test]
+  fn a_generic_type_ends_its_line() {
+    let code = "Array<Integer> xs = ys as Array<Integer>\nprintln(xs)";
+    assert_eq!(texts(code, &parse(code)), vec!("Array<Integer> xs = ys as Array<Integer>", "println(xs)"));
+  }
+
+  #[test]
+  fn doc_comments_attach_to_the_declaration_below() {
+    let program = parse("/// Adds one\n/// to x.\nfunk inc(Integer x) Integer { return x + 1 }\n/** The answer */\nInteger a = 42\n\n\
+      /// Stray\nprintln(1)\nenum Color { Red }\n/// Kept // not this\n// plain\nklass K { }");
+    let docs: Vec<Option<String>> = program.iter().filter_map(|ast| match ast {
+      AST::Statement(Statement::Function(decl), _) => Some(decl.doc.clone()),
+      AST::Statement(Statement::Assignment(assign), _) => Some(assign.doc.clone()),
+      AST::Statement(Statement::Enum(decl), _) => Some(decl.doc.clone()),
+      AST::Statement(Statement::Klass(decl), _) => Some(decl.doc.clone()),
+      _ => None
+    }).collect();
+    assert_eq!(docs, vec!(Some("Adds one\nto x.".to_string()), Some("The answer".to_string()), None, None));
+  }
+
+  #[test]
+  fn empty_input() {
+    for code in ["", "\n\n", ";\n;", "// nothing here\n/* or here */\n"] {
+      let tree = Parser::new(Box::new(Lexer::new(code)), code, FileId(0)).parse().unwrap();
+      assert!(tree.children().is_empty(), "{:?}", code);
+      assert_eq!(tree.text(), code);
+    }
+  }
+
+  #[test]
+  fn only_the_lookahead_stays_buffered() {
+    let code = "/// Counts.\nfunk f(Integer a) Integer { return a }\n".to_string() + &"// again\nInteger x = f(1) + 2\n".repeat(1000);
+    let pulled = std::rc::Rc::new(std::cell::Cell::new(0));
+    let counter = pulled.clone();
+    let tokens = Lexer::new(&code).inspect(move |_| counter.set(counter.get() + 1));
+    let mut parser = Parser::new(Box::new(tokens), &code, FileId(0));
+    let tree = parser.parse().unwrap();
+    assert_eq!(tree.children().len(), 1001);
+    assert!(parser.tokens.len() <= 1 && parser.trivia.is_empty(), "{} tokens left", parser.tokens.len());
+    assert_eq!(pulled.get(), Lexer::new(&code).count());
+    assert_eq!(tree.text(), code);
+  }
+
+  #[test]
+  fn try_with_typed_catches_and_finally() {
+    let program = parse("try {\n  throw Error(\"no\")\n}\ncatch (Error e) { 1 } catch (String s) { 2 }\nfinally { 3 }");
+    let try_ = match &program[0] {
+      AST::Statement(Statement::Try(try_), _) => try_,
+      other => panic!("Expected a try, found {:?}", other)
+    };
+    assert!(matches!(&try_.body[0], AST::Statement(Statement::Throw(_), _)));
+    assert_eq!(try_.catches.iter().map(|catch| (catch.ty.clone(), catch.name.as_str())).collect::<Vec<(Type, &str)>>(),
+      vec!((Type::Named("Error".to_string(), vec!()), "e"), (Type::String, "s")));
+    assert_eq!(try_.finally.as_ref().map(|body| body.len()), Some(1));
+  }
+
+  #[test]
+  fn running_out_of_input_is_reported_where_it_ends() {
+    let code = "Integer a = 1 +";
+    let error = Parser::new(Box::new(Lexer::new(code)), code, FileId(0)).parse().unwrap_err();
+    assert_eq!((error.message.as_str(), error.span.range), ("Unexpected end of input", 15..15));
+
+    let code = "funk f() Integer {\n  return 1";
+    let error = Parser::new(Box::new(Lexer::new(code)), code, FileId(0)).parse().unwrap_err();
+    assert_eq!((error.message.as_str(), error.span.range), ("Unexpected end of input, expected [[RCurl]]", 29..29));
+  }
+
+  #[test]
+  fn lexer_errors_are_reported_at_the_token() {
+    let code = "Integer a = 1\nString s = \"abc\n";
+    let error = Parser::new(Box::new(Lexer::new(code)), code, FileId(0)).parse().unwrap_err();
+    assert_eq!((error.message.as_str(), error.span.range), ("Unterminated string", 25..30));
+  }
+
+  #[test]
+  fn try_needs_a_clause() {
+    assert_eq!(error("try { 1 }\nInteger a = 2"), ("`try` needs a catch or a finally".to_string(), 0..9));
+  }
+
+  #[test]
+  #[should_panic(expected = "[Hash] takes 2 type arguments, found 1")]
+  fn generic_arity_is_checked() {
+    parse("Hash<String> h = null");
+  }
+
+  #[test]
+  fn newline_before_an_operator_ends_the_statement() {
+    assert_eq!(error("Integer a = 1\n  + 2"), ("Unexpected token [Plus]".to_string(), 16..17));
+  }
+
+  #[test]
+  fn statements_need_a_terminator() {
+    assert_eq!(error("Integer a = 1 Integer b = 2"),
+      ("Expected a newline or [;] after the statement, instead found [Keyword(Integer)]".to_string(), 14..21));
+    assert_eq!(error("funk f() { return 1 2 }"),
+      ("Expected a newline or [;] after the statement, instead found [Literal(Number)]".to_string(), 20..21));
+  }
+
+  #[test]
+  fn unexpected_tokens_are_reported_where_they_are() {
+    assert_eq!(error("Integer a = )"), ("Unexpected token [RPar]".to_string(), 12..13));
+    assert_eq!(error("Integer = 1"), ("Expected [[Identifier]], instead found [Equals]".to_string(), 8..9));
+    assert_eq!(error("enum E { A, A }"), ("Variant [A] is declared twice in enum [E]".to_string(), 12..13));
+    assert_eq!(error("funk f(, Integer a) {}"), ("Expected a parameter before [,]".to_string(), 7..8));
   }
 }
@@ -0,0 +1,172 @@
+use std::ops::Range;
+
+// Converts between byte offsets, which tokens and the parser use, and the line and column
+// positions people and editors use. Columns come as char counts and as UTF-16 code units.
+pub struct SourceMap {
+  source: String,
+  lines: Vec<LineStart>
+}
+
+struct LineStart {
+  byte: usize,
+  char: usize
+}
+
+// Index into `Files`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileId(pub usize);
+
+// Where a node came from: its file and the byte range it covers there.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Span {
+  pub file: FileId,
+  pub range: Range<usize>
+}
+
+// Every source file of a run, with a map for turning spans back into lines and columns.
+pub struct Files {
+  files: Vec<(String, SourceMap)>
+}
+
+// Zero based, add one when showing it to a person.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+  pub line: usize,
+  pub column: usize,
+  pub utf16_column: usize
+}
+
+#[allow(dead_code)]
+impl SourceMap {
+  pub fn new(source: &str) -> SourceMap {
+    let mut lines = vec!(LineStart{ byte: 0, char: 0 });
+    for (char, (byte, c)) in source.char_indices().enumerate() {
+      if c == '\n' {
+        lines.push(LineStart{ byte: byte + 1, char: char + 1 });
+      }
+    }
+    SourceMap{ source: source.to_string(), lines }
+  }
+
+  pub fn position(&self, byte: usize) -> Position {
+    let line = self.lines.partition_point(|start| start.byte <= byte) - 1;
+    let before = &self.source[self.lines[line].byte..byte.min(self.source.len())];
+    Position{ line, column: before.chars().count(), utf16_column: before.encode_utf16().count() }
+  }
+
+  // Byte offset of the char at `index`, the length of the source when past the end.
+  pub fn byte_offset(&self, index: usize) -> usize {
+    let line = &self.lines[self.lines.partition_point(|start| start.char <= index) - 1];
+    match self.source[line.byte..].char_indices().nth(index - line.char) {
+      Some((byte, _)) => line.byte + byte,
+      None => self.source.len()
+    }
+  }
+
+  pub fn byte_of_column(&self, line: usize, column: usize) -> Option<usize> {
+    let text = self.line_text(line)?;
+    if column == text.chars().count() {
+      return Some(self.lines[line].byte + text.len());
+    }
+    text.char_indices().nth(column).map(|(byte, _)| self.lines[line].byte + byte)
+  }
+
+  // None when the column is past the line or splits a surrogate pair.
+  pub fn byte_of_utf16_column(&self, line: usize, utf16_column: usize) -> Option<usize> {
+    let text = self.line_text(line)?;
+    let mut units = 0;
+    for (byte, c) in text.char_indices() {
+      if units == utf16_column {
+        return Some(self.lines[line].byte + byte);
+      }
+      units += c.len_utf16();
+    }
+    if units == utf16_column { Some(self.lines[line].byte + text.len()) } else { None }
+  }
+
+  // The line without its line break.
+  pub fn line_text(&self, line: usize) -> Option<&str> {
+    let start = self.lines.get(line)?.byte;
+    let end = self.lines.get(line + 1).map(|next| next.byte).unwrap_or(self.source.len());
+    Some(self.source[start..end].trim_end_matches('\n').trim_end_matches('\r'))
+  }
+}
+
+#[allow(dead_code)]
+impl Files {
+  pub fn new() -> Files {
+    Files{ files: vec!() }
+  }
+
+  pub fn add(&mut self, name: &str, source: &str) -> FileId {
+    self.files.push((name.to_string(), SourceMap::new(source)));
+    FileId(self.files.len() - 1)
+  }
+
+  pub fn name(&self, file: FileId) -> &str {
+    &self.files[file.0].0
+  }
+
+  pub fn map(&self, file: FileId) -> &SourceMap {
+    &self.files[file.0].1
+  }
+
+  pub fn get(&self, file: FileId) -> Option<&SourceMap> {
+    self.files.get(file.0).map(|(_, map)| map)
+  }
+
+  // `name:line:column`, one based.
+  pub fn location(&self, span: &Span) -> String {
+    match self.files.get(span.file.0) {
+      Some((name, map)) => {
+        let position = map.position(span.range.start);
+        format!("{}:{}:{}", name, position.line + 1, position.column + 1)
+      },
+      None => "<unknown>".to_string()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use lexer::Lexer;
+  use structs::structs::*;
+  use super::*;
+
+  #[test]
+  fn positions_count_chars_and_utf16_units() {
+    let map = SourceMap::new("a = 1\nnäme = \"😀x\"");
+    let x = "a = 1\nnäme = \"😀x\"".find('x').unwrap();
+
+    assert_eq!(map.position(0), Position{ line: 0, column: 0, utf16_column: 0 });
+    assert_eq!(map.position(x), Position{ line: 1, column: 9, utf16_column: 10 });
+    assert_eq!(map.byte_of_column(1, 9), Some(x));
+    assert_eq!(map.byte_of_utf16_column(1, 10), Some(x));
+    assert_eq!(map.byte_of_utf16_column(1, 9), None);
+    assert_eq!(map.byte_offset(15), x);
+    assert_eq!(map.line_text(1), Some("näme = \"😀x\""));
+  }
+
+  #[test]
+  fn tokens_slice_multibyte_source() {
+    let code = "String grüße = \"日本語\"\nInteger ünïcode = 1";
+    let tokens = Lexer::new(code).map(Result::unwrap).collect::<Vec<Token>>();
+    let texts: Vec<&str> = tokens.iter().map(|token| &code[token.range.clone()]).collect();
+
+    assert_eq!(texts, vec!("String", "grüße", "=", "\"日本語\"", "\n", "Integer", "ünïcode", "=", "1"));
+    let last = tokens.last().unwrap();
+    assert_eq!((last.ty.clone(), last.line, last.column, last.utf16_column), (TokenType::Literal(Val::Number), 1, 18, 18));
+    let string = &tokens[3];
+    assert_eq!((string.line, string.column, string.utf16_column), (0, 15, 15));
+  }
+
+  #[test]
+  fn utf16_columns_count_surrogate_pairs() {
+    let code = "\"🎉🎉\" == x";
+    let tokens = Lexer::new(code).map(Result::unwrap).collect::<Vec<Token>>();
+    let x = tokens.last().unwrap();
+
+    assert_eq!(&code[x.range.clone()], "x");
+    assert_eq!((x.column, x.utf16_column), (8, 10));
+  }
+}
@@ -1,27 +1,53 @@
 pub mod structs {
-    use std::ops::{Add, Range};
+    use std::ops::Range;
     use std::collections::HashMap;
+    use std::fmt;
+    use std::rc::Rc;
+    use bigint::BigInt;
+    use interner::Symbol;
+    use source_map::Span;
 
-    pub const OPEARTORS: [&'static str; 28] = [
+    pub const OPEARTORS: [&str; 34] = [
         "+", "-", "*", "/", "%", "^", ">", "<", ">=", "<=", "==", "!=", "!", "&", ":", "?", "|",
-        "::", "#", "&&", "||", "++", "--", "=", "###", ".", "..", "...",
+        "::", "&&", "||", "++", "--", "=", ".", "..", "...", "=>", "?.", "?:", "+=", "-=", "*=", "/=", "%=",
     ];
-    pub const KEYWORDS: [&'static str; 18] = [
+    pub const KEYWORDS: [&str; 29] = [
         "funk", "while", "for", "when", "if", "elseif", "else", "Integer", "String", "Decimal",
-        "klass", "in", "return", "Boolean", "Array", "Hash", "mut", "Void",
+        "klass", "in", "return", "Boolean", "Array", "Hash", "mut", "Void", "enum",
+        "interface", "implements", "null", "let", "BigInteger", "as", "try", "catch", "finally", "throw",
     ];
 
     #[derive(Debug, Clone, PartialEq)]
     pub struct Token {
         pub ty: TokenType,
+        pub value: TokenValue,
+        // Zero based line and column of the first char, columns in chars and in UTF-16 code units.
         pub line: usize,
+        pub column: usize,
+        pub utf16_column: usize,
+        // Byte offsets into the source.
         pub range: Range<usize>
     }
 
+    // What the lexer already worked out about a token, so nobody has to look at the source again.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum TokenValue {
+        None,
+        // Identifiers and keywords.
+        Symbol(Symbol),
+        Integer(i64),
+        BigInteger(BigInt),
+        Decimal(f64),
+        // Unescaped string literals and comment text.
+        String(String)
+    }
+
     #[derive(Debug, Clone, PartialEq)]
     pub enum TokenType {
         // \n
         Newline,
+        // Spaces and tabs, only found in the syntax tree, the lexer skips them.
+        Whitespace,
         // (
         LPar,
         // )
@@ -41,8 +67,8 @@ pub mod structs {
         Literal(Val),
         Keyword(Keyword),
         Identifier,
-        // / Single Line - # Multi Line #
-        Comment,
+        // // Single Line - /* Multi Line */, never reaches the grammar.
+        Comment(CommentKind),
         // ++
         PlusPlus,
         // --
@@ -61,6 +87,8 @@ pub mod structs {
         Percent,
         // .
         Dot,
+        // ..
+        DotDot,
         // =
         Equals,
         // ==
@@ -83,41 +111,79 @@ pub mod structs {
         PlusEqual,
         // -=
         MinusEqual,
+        // *=
+        StarEqual,
+        // /=
+        SlashEqual,
+        // %=
+        PercentEqual,
         // !
         Not,
+        // ::
+        ColonColon,
+        // =>
+        FatArrow,
+        // ?
+        Question,
+        // ?.
+        QuestionDot,
+        // ?:
+        Elvis,
         // Unexpected
         Unknown
     }
 
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum CommentKind {
+        // // ...
+        Line,
+        // /* ... */
+        Block,
+        // /// ... or /** ... */, documents the declaration after it.
+        Doc
+    }
+
     impl TokenType {
         pub fn new(raw: String) -> TokenType {
             match raw {
-                c if c == "+".to_string() => TokenType::Plus,
-                c if c == "-".to_string() => TokenType::Minus,
-                c if c == "/".to_string() => TokenType::Slash,
-                c if c == "*".to_string() => TokenType::Star,
-                c if c == "**".to_string() => TokenType::StarStar,
-                c if c == "%".to_string() => TokenType::Percent,
-                c if c == "==".to_string() => TokenType::EqualsEquals,
-                c if c == "=".to_string() => TokenType::Equals,
-                c if c == "<".to_string() => TokenType::LessThan,
-                c if c == ">".to_string() => TokenType::GreaterThan,
-                c if c == ">=".to_string() => TokenType::GreaterOrEqual,
-                c if c == "<=".to_string() => TokenType::LessThanOrEqual,
-                c if c == "!".to_string() => TokenType::Not,
-                c if c == "!=".to_string() => TokenType::NotEqual,
-                c if c == "&&".to_string() => TokenType::And,
-                c if c == "||".to_string() => TokenType::Or,
-                c if c == "+=".to_string() => TokenType::PlusEqual,
-                c if c == "-=".to_string() => TokenType::MinusEqual,
-                c if c == "(".to_string() => TokenType::LPar,
-                c if c == ")".to_string() => TokenType::RPar,
-                c if c == "[".to_string() => TokenType::LBrac,
-                c if c == "]".to_string() => TokenType::RBrac,
-                c if c == "{".to_string() => TokenType::LCurl,
-                c if c == "}".to_string() => TokenType::RCurl,
-                c if c == ",".to_string() => TokenType::Comma,
-                c if c == ";".to_string() => TokenType::Semi,
+                c if c == "+" => TokenType::Plus,
+                c if c == "-" => TokenType::Minus,
+                c if c == "/" => TokenType::Slash,
+                c if c == "*" => TokenType::Star,
+                c if c == "**" => TokenType::StarStar,
+                c if c == "%" => TokenType::Percent,
+                c if c == "==" => TokenType::EqualsEquals,
+                c if c == "=" => TokenType::Equals,
+                c if c == "<" => TokenType::LessThan,
+                c if c == ">" => TokenType::GreaterThan,
+                c if c == ">=" => TokenType::GreaterOrEqual,
+                c if c == "<=" => TokenType::LessThanOrEqual,
+                c if c == "!" => TokenType::Not,
+                c if c == "!=" => TokenType::NotEqual,
+                c if c == "&&" => TokenType::And,
+                c if c == "||" => TokenType::Or,
+                c if c == "+=" => TokenType::PlusEqual,
+                c if c == "-=" => TokenType::MinusEqual,
+                c if c == "*=" => TokenType::StarEqual,
+                c if c == "/=" => TokenType::SlashEqual,
+                c if c == "%=" => TokenType::PercentEqual,
+                c if c == "++" => TokenType::PlusPlus,
+                c if c == "--" => TokenType::MinusMinus,
+                c if c == "." => TokenType::Dot,
+                c if c == ".." => TokenType::DotDot,
+                c if c == "::" => TokenType::ColonColon,
+                c if c == "=>" => TokenType::FatArrow,
+                c if c == "?" => TokenType::Question,
+                c if c == "?." => TokenType::QuestionDot,
+                c if c == "?:" => TokenType::Elvis,
+                c if c == "(" => TokenType::LPar,
+                c if c == ")" => TokenType::RPar,
+                c if c == "[" => TokenType::LBrac,
+                c if c == "]" => TokenType::RBrac,
+                c if c == "{" => TokenType::LCurl,
+                c if c == "}" => TokenType::RCurl,
+                c if c == "," => TokenType::Comma,
+                c if c == ";" => TokenType::Semi,
                 _ => TokenType::Unknown
             }
         }
@@ -128,6 +194,9 @@ pub mod structs {
         Integer,
         Decimal,
         Boolean,
+        String,
+        Array,
+        Hash,
         If,
         Else,
         Elseif,
@@ -136,6 +205,21 @@ pub mod structs {
         For,
         Break,
         Funk,
+        Enum,
+        When,
+        Klass,
+        Interface,
+        Implements,
+        Void,
+        Null,
+        Let,
+        BigInteger,
+        As,
+        Try,
+        Catch,
+        Finally,
+        Throw,
+        Mut,
         Unknown
     }
 
@@ -145,6 +229,9 @@ pub mod structs {
                 c if c == "Integer" => Keyword::Integer,
                 c if c == "Decimal" => Keyword::Decimal,
                 c if c == "Boolean" => Keyword::Boolean,
+                c if c == "String" => Keyword::String,
+                c if c == "Array" => Keyword::Array,
+                c if c == "Hash" => Keyword::Hash,
                 c if c == "if" => Keyword::If,
                 c if c == "else" => Keyword::Else,
                 c if c == "elseif" => Keyword::Elseif,
@@ -153,6 +240,21 @@ pub mod structs {
                 c if c == "for" => Keyword::For,
                 c if c == "break" => Keyword::Break,
                 c if c == "funk" => Keyword::Funk,
+                c if c == "enum" => Keyword::Enum,
+                c if c == "when" => Keyword::When,
+                c if c == "klass" => Keyword::Klass,
+                c if c == "interface" => Keyword::Interface,
+                c if c == "implements" => Keyword::Implements,
+                c if c == "Void" => Keyword::Void,
+                c if c == "null" => Keyword::Null,
+                c if c == "let" => Keyword::Let,
+                c if c == "BigInteger" => Keyword::BigInteger,
+                c if c == "as" => Keyword::As,
+                c if c == "try" => Keyword::Try,
+                c if c == "catch" => Keyword::Catch,
+                c if c == "finally" => Keyword::Finally,
+                c if c == "throw" => Keyword::Throw,
+                c if c == "mut" => Keyword::Mut,
                 _  => Keyword::Unknown,
             }
         }
@@ -167,49 +269,494 @@ pub mod structs {
     #[derive(Debug, Clone, PartialEq)]
     pub enum Type {
         Integer,
-        Decimal
+        Decimal,
+        BigInteger,
+        Boolean,
+        String,
+        // Array<Integer>
+        Array(Box<Type>),
+        // Hash<String, Integer>, keys then values.
+        Hash(Box<Type>, Box<Type>),
+        // A user declared or builtin enum, klass or interface, by name, with the type arguments of a generic enum.
+        Named(String, Vec<Type>),
+        // Integer?, may also hold null.
+        Nullable(Box<Type>),
+        // Result of a function that returns nothing.
+        Void,
+        // funk(Integer, Decimal) Integer
+        Function(Vec<Type>, Box<Type>),
+        // Left out in the source (`let`, lambda parameters), filled in by the checker.
+        Infer,
+        // Holds any value, like what json::parse gives back. Getting a typed value out takes an `as` cast.
+        Any,
+        // T in the signature of a generic native, stands for whatever type the call site uses.
+        Param(String)
+    }
+
+    impl Type {
+        pub fn is_nullable(&self) -> bool {
+            matches!(self, Type::Nullable(_))
+        }
+
+        // Integer? -> Integer
+        pub fn non_null(&self) -> Type {
+            match self {
+                Type::Nullable(inner) => *inner.clone(),
+                ty => ty.clone()
+            }
+        }
+
+        // False while some part of the type is still to be inferred, like the T of `Option::None`.
+        pub fn is_known(&self) -> bool {
+            match self {
+                Type::Infer => false,
+                Type::Array(inner) | Type::Nullable(inner) => inner.is_known(),
+                Type::Hash(key, value) => key.is_known() && value.is_known(),
+                Type::Named(_, args) => args.iter().all(Type::is_known),
+                Type::Function(params, ret) => params.iter().all(Type::is_known) && ret.is_known(),
+                _ => true
+            }
+        }
+
+        // Works out the type parameters in `self` from the `found` type standing in its place, the first
+        // binding of a parameter wins.
+        pub fn bind(&self, found: &Type, bound: &mut HashMap<String, Type>) {
+            match (self, found) {
+                (Type::Param(name), found) if *found != Type::Infer => {
+                    bound.entry(name.clone()).or_insert_with(|| found.clone());
+                },
+                (Type::Array(param), Type::Array(found)) | (Type::Nullable(param), Type::Nullable(found)) => param.bind(found, bound),
+                (Type::Named(name, params), Type::Named(found_name, found)) if name == found_name => {
+                    for (param, found) in params.iter().zip(found) {
+                        param.bind(found, bound);
+                    }
+                },
+                (Type::Nullable(param), found) => param.bind(found, bound),
+                (Type::Hash(key, value), Type::Hash(found_key, found_value)) => {
+                    key.bind(found_key, bound);
+                    value.bind(found_value, bound);
+                },
+                (Type::Function(params, ret), Type::Function(found_params, found_ret)) => {
+                    for (param, found) in params.iter().zip(found_params) {
+                        param.bind(found, bound);
+                    }
+                    ret.bind(found_ret, bound);
+                },
+                _ => {}
+            }
+        }
+
+        // `self` with the bound type parameters replaced.
+        pub fn substitute(&self, bound: &HashMap<String, Type>) -> Type {
+            match self {
+                Type::Param(name) => bound.get(name).cloned().unwrap_or_else(|| self.clone()),
+                Type::Array(item) => Type::Array(Box::new(item.substitute(bound))),
+                Type::Hash(key, value) => Type::Hash(Box::new(key.substitute(bound)), Box::new(value.substitute(bound))),
+                Type::Nullable(inner) => Type::Nullable(Box::new(inner.substitute(bound))),
+                Type::Named(name, args) => Type::Named(name.clone(), args.iter().map(|arg| arg.substitute(bound)).collect()),
+                Type::Function(params, ret) => {
+                    Type::Function(params.iter().map(|p| p.substitute(bound)).collect(), Box::new(ret.substitute(bound)))
+                },
+                ty => ty.clone()
+            }
+        }
+    }
+
+    impl fmt::Display for Type {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Type::Integer => write!(f, "Integer"),
+                Type::Decimal => write!(f, "Decimal"),
+                Type::BigInteger => write!(f, "BigInteger"),
+                Type::Boolean => write!(f, "Boolean"),
+                Type::String => write!(f, "String"),
+                Type::Array(item) => write!(f, "Array<{}>", item),
+                Type::Hash(key, value) => write!(f, "Hash<{}, {}>", key, value),
+                Type::Named(name, args) if args.is_empty() => write!(f, "{}", name),
+                Type::Named(name, args) => {
+                    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                    write!(f, "{}<{}>", name, args.join(", "))
+                },
+                Type::Nullable(inner) => write!(f, "{}?", inner),
+                Type::Void => write!(f, "Void"),
+                Type::Function(params, ret) => {
+                    let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                    write!(f, "funk({}) {}", params.join(", "), ret)
+                },
+                Type::Infer => write!(f, "_"),
+                Type::Any => write!(f, "Any"),
+                Type::Param(name) => write!(f, "{}", name)
+            }
+        }
+    }
+
+    // A type as written, `to_type` resolves the builtin names and checks generic arguments.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum TypeExpr {
+        // Integer, Shape, Array<Integer> or Hash<String, Integer>
+        Name(String, Vec<TypeExpr>),
+        // funk(Integer, Decimal) Integer
+        Function(Vec<TypeExpr>, Box<TypeExpr>),
+        // Integer?
+        Nullable(Box<TypeExpr>)
+    }
+
+    impl TypeExpr {
+        pub fn to_type(&self) -> Result<Type, String> {
+            match self {
+                TypeExpr::Nullable(inner) => match inner.to_type()? {
+                    Type::Void => Err("Void cannot be nullable".to_string()),
+                    ty => Ok(Type::Nullable(Box::new(ty)))
+                },
+                TypeExpr::Function(params, ret) => {
+                    let params = params.iter().map(TypeExpr::to_type).collect::<Result<Vec<Type>, String>>()?;
+                    Ok(Type::Function(params, Box::new(ret.to_type()?)))
+                },
+                TypeExpr::Name(name, args) => {
+                    let args = args.iter().map(TypeExpr::to_type).collect::<Result<Vec<Type>, String>>()?;
+                    match (name.as_str(), args.as_slice()) {
+                        ("Integer", []) => Ok(Type::Integer),
+                        ("Decimal", []) => Ok(Type::Decimal),
+                        ("BigInteger", []) => Ok(Type::BigInteger),
+                        ("Boolean", []) => Ok(Type::Boolean),
+                        ("String", []) => Ok(Type::String),
+                        ("Void", []) => Ok(Type::Void),
+                        ("Any", []) => Ok(Type::Any),
+                        ("Array", [item]) => Ok(Type::Array(Box::new(item.clone()))),
+                        ("Hash", [key, value]) => Ok(Type::Hash(Box::new(key.clone()), Box::new(value.clone()))),
+                        ("Array", _) => Err(format!("[Array] takes 1 type argument, found {}", args.len())),
+                        ("Hash", _) => Err(format!("[Hash] takes 2 type arguments, found {}", args.len())),
+                        // Whether an enum takes these type arguments is up to the checker, which knows its declaration.
+                        (name, args) => Ok(Type::Named(name.to_string(), args.to_vec()))
+                    }
+                }
+            }
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
+    #[allow(clippy::upper_case_acronyms)]
     pub enum AST {
-        Expression(Expr),
-        Statement(Statement)
+        Expression(Expr, Span),
+        Statement(Statement, Span)
     }
 
     #[derive(Debug, Clone, PartialEq)]
     pub enum Statement {
-        Assignment(Assign)
+        Assignment(Assign),
+        Enum(EnumDecl),
+        Function(FunctionDecl),
+        Klass(KlassDecl),
+        Interface(InterfaceDecl),
+        // `return` on its own leaves a Void function.
+        Return(Option<Box<AST>>),
+        // x = 1, p.x = 1 or xs[0] = 1. `x += 1` has an op, the place is looked up once to read and write it.
+        Reassign{
+            target: Box<AST>,
+            op: Option<BinOp>,
+            value: Box<AST>
+        },
+        // Any value can be thrown, not only an Error.
+        Throw(Box<AST>),
+        Try(Try)
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Try {
+        pub body: Vec<AST>,
+        // Tried in order, the first whose type matches the thrown value runs.
+        pub catches: Vec<Catch>,
+        pub finally: Option<Vec<AST>>
+    }
+
+    // catch (Error e) { ... }
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Catch {
+        pub ty: Type,
+        pub name: String,
+        pub body: Vec<AST>
     }
 
     #[derive(Debug, Clone, PartialEq)]
     pub struct Assign {
         pub name: String,
         pub ty: Type,
-        pub value: Box<AST>
+        pub value: Box<AST>,
+        // Declared with `mut`, only then can it be assigned to again.
+        pub mutable: bool,
+        pub doc: Option<String>
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct EnumDecl {
+        pub name: String,
+        // Type parameters, T in Option<T>. Only the builtin enums have them.
+        pub params: Vec<String>,
+        pub variants: Vec<EnumVariant>,
+        pub doc: Option<String>
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct EnumVariant {
+        pub name: String,
+        pub fields: Vec<Type>
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct FunctionDecl {
+        pub name: String,
+        // Expr::Argument
+        pub params: Vec<AST>,
+        // Void when left out.
+        pub return_type: Type,
+        pub body: Vec<AST>,
+        pub doc: Option<String>,
+        pub span: Span
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct KlassDecl {
+        pub name: String,
+        pub interfaces: Vec<String>,
+        // Expr::Argument, in constructor order.
+        pub fields: Vec<AST>,
+        // Names of the fields declared with `mut`, the others keep what the constructor gave them.
+        pub mutable: Vec<String>,
+        pub methods: Vec<FunctionDecl>,
+        pub doc: Option<String>
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct InterfaceDecl {
+        pub name: String,
+        pub methods: Vec<MethodSignature>,
+        pub doc: Option<String>
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct MethodSignature {
+        pub name: String,
+        pub params: Vec<Type>,
+        pub return_type: Type
+    }
+
+    impl fmt::Display for MethodSignature {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let params: Vec<String> = self.params.iter().map(|p| p.to_string()).collect();
+            write!(f, "funk {}({}) {}", self.name, params.join(", "), self.return_type)
+        }
+    }
+
+    impl FunctionDecl {
+        pub fn signature(&self) -> MethodSignature {
+            MethodSignature{ name: self.name.clone(), params: self.params.iter().map(|p| p.argument().1).collect(), return_type: self.return_type.clone() }
+        }
+
+        pub fn ty(&self) -> Type {
+            let signature = self.signature();
+            Type::Function(signature.params, Box::new(signature.return_type))
+        }
+    }
+
+    impl AST {
+        pub fn span(&self) -> &Span {
+            match self {
+                AST::Expression(_, span) | AST::Statement(_, span) => span
+            }
+        }
+
+        // A funk, klass, interface or enum, which the whole program sees.
+        pub fn is_declaration(&self) -> bool {
+            matches!(self, AST::Statement(Statement::Function(_) | Statement::Klass(_) | Statement::Interface(_) | Statement::Enum(_), _))
+        }
+
+        // Whether running this never carries on to the next statement, because it returns or throws
+        // on every path.
+        pub fn always_leaves(&self) -> bool {
+            match self {
+                AST::Statement(Statement::Return(_) | Statement::Throw(_), _) => true,
+                AST::Statement(Statement::Try(try_), _) => {
+                    try_.finally.as_ref().is_some_and(|finally| finally.iter().any(AST::always_leaves))
+                        || (try_.body.iter().any(AST::always_leaves) && try_.catches.iter().all(|catch| catch.body.iter().any(AST::always_leaves)))
+                },
+                AST::Expression(Expr::When(when), _) => when.arms.iter().all(|arm| arm.body.iter().any(AST::always_leaves)),
+                _ => false
+            }
+        }
+
+        // `x`, `p.x` or `xs[i]` when this names something that can be assigned to.
+        pub fn place(&self) -> Option<String> {
+            match self {
+                AST::Expression(Expr::Identifier(name), _) => Some(name.clone()),
+                AST::Expression(Expr::Field{ object, field, safe: false }, _) => object.place().map(|object| format!("{}.{}", object, field)),
+                AST::Expression(Expr::Index{ object, index }, _) => {
+                    let index = match &index.as_ref() {
+                        AST::Expression(Expr::Integer(value), _) => value.to_string(),
+                        index => index.place().unwrap_or("..".to_string())
+                    };
+                    object.place().map(|object| format!("{}[{}]", object, index))
+                },
+                _ => None
+            }
+        }
+
+        // Name and type of an `Expr::Argument`.
+        pub fn argument(&self) -> (String, Type) {
+            match self {
+                AST::Expression(Expr::Argument{ name, ty }, _) => (name.clone(), ty.clone()),
+                _ => panic!("Expected an argument, instead found [{:?}]", self)
+            }
+        }
+    }
+
+    impl KlassDecl {
+        // Error is what the interpreter throws itself. `Error(message)` makes one, the trace is
+        // filled in where it is thrown.
+        pub fn builtins() -> Vec<KlassDecl> {
+            let field = |name: &str, ty: Type| AST::Expression(Expr::Argument{ name: name.to_string(), ty }, Span::default());
+            vec!(
+                KlassDecl{ name: "Error".to_string(), interfaces: vec!(), fields: vec!(
+                    field("message", Type::String),
+                    field("trace", Type::String)
+                ), mutable: vec!(), methods: vec!(), doc: None }
+            )
+        }
+    }
+
+    impl EnumDecl {
+        // Option<T> and Result<T, E> let code report absence or failure as a value instead of a panic.
+        pub fn builtins() -> Vec<EnumDecl> {
+            let param = |name: &str| Type::Param(name.to_string());
+            vec!(
+                EnumDecl{ name: "Option".to_string(), params: vec!("T".to_string()), variants: vec!(
+                    EnumVariant{ name: "Some".to_string(), fields: vec!(param("T")) },
+                    EnumVariant{ name: "None".to_string(), fields: vec!() }
+                ), doc: None },
+                EnumDecl{ name: "Result".to_string(), params: vec!("T".to_string(), "E".to_string()), variants: vec!(
+                    EnumVariant{ name: "Ok".to_string(), fields: vec!(param("T")) },
+                    EnumVariant{ name: "Err".to_string(), fields: vec!(param("E")) }
+                ), doc: None }
+            )
+        }
+
+        pub fn variant(&self, name: &str) -> Option<&EnumVariant> {
+            self.variants.iter().find(|v| v.name == name)
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
+    #[allow(dead_code)]
     pub enum Expr {
         Integer(i64),
         Decimal(f64),
+        // Integer literals too large for an i64.
+        BigInteger(BigInt),
         String(String),
         Boolean(bool),
+        Null,
         Binary(BinaryExpr),
+        Identifier(String),
         Argument{
             name: String,
-            ty: Keyword
+            ty: Type
+        },
+        Call{
+            name: String,
+            args: Vec<AST>
+        },
+        // a.b, or a?.b when safe
+        Field{
+            object: Box<AST>,
+            field: String,
+            safe: bool
+        },
+        // xs[0]
+        Index{
+            object: Box<AST>,
+            index: Box<AST>
+        },
+        // a.b(), or a?.b() when safe
+        MethodCall{
+            object: Box<AST>,
+            method: String,
+            args: Vec<AST>,
+            safe: bool
+        },
+        // funk(x) { x + 1 }
+        Lambda(FunctionDecl),
+        // a ?: b
+        Elvis{
+            left: Box<AST>,
+            right: Box<AST>
+        },
+        // Shape::Circle(1.5)
+        Variant{
+            enum_name: String,
+            variant: String,
+            args: Vec<AST>
+        },
+        When(When),
+        // x as Decimal
+        Cast{
+            value: Box<AST>,
+            ty: Type
+        },
+        // ++x or x--, Add or Subtract one. Evaluates to the new value when prefix, the old one otherwise.
+        Increment{
+            target: Box<AST>,
+            op: BinOp,
+            prefix: bool
         }
     }
 
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct When {
+        pub subject: Box<AST>,
+        pub arms: Vec<WhenArm>
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct WhenArm {
+        pub pattern: Pattern,
+        pub body: Vec<AST>
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Pattern {
+        // Shape::Rect(w, h)
+        Variant{
+            enum_name: String,
+            variant: String,
+            bindings: Vec<String>
+        },
+        // _
+        Wildcard
+    }
+
     #[derive(Debug, Clone, PartialEq)]
     pub enum BinOp {
         Add,
         Subtract,
         Multiply,
         Divide,
+        Modulo,
         Exponent,
         Unknown
     }
+    impl fmt::Display for BinOp {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                BinOp::Add => write!(f, "+"),
+                BinOp::Subtract => write!(f, "-"),
+                BinOp::Multiply => write!(f, "*"),
+                BinOp::Divide => write!(f, "/"),
+                BinOp::Modulo => write!(f, "%"),
+                BinOp::Exponent => write!(f, "**"),
+                BinOp::Unknown => write!(f, "?")
+            }
+        }
+    }
+
     impl BinOp {
         pub fn new(tkty: &TokenType) -> BinOp {
             match  tkty {
@@ -217,10 +764,23 @@ pub mod structs {
                 TokenType::Minus => BinOp::Subtract,
                 TokenType::Star => BinOp::Multiply,
                 TokenType::Slash => BinOp::Divide,
+                TokenType::Percent => BinOp::Modulo,
                 TokenType::StarStar => BinOp::Exponent,
                 _ => BinOp::Unknown
             }
         }
+
+        // The operator inside `+=` and friends.
+        pub fn compound(tkty: &TokenType) -> Option<BinOp> {
+            match tkty {
+                TokenType::PlusEqual => Some(BinOp::Add),
+                TokenType::MinusEqual => Some(BinOp::Subtract),
+                TokenType::StarEqual => Some(BinOp::Multiply),
+                TokenType::SlashEqual => Some(BinOp::Divide),
+                TokenType::PercentEqual => Some(BinOp::Modulo),
+                _ => None
+            }
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -231,13 +791,28 @@ pub mod structs {
     }
 
     #[derive(Debug, Clone, PartialEq)]
+    #[allow(dead_code)]
     pub enum Object {
         Integer(Integer),
         Decimal(Decimal),
+        BigInteger(BigInteger),
+        String(Str),
+        Boolean(bool),
+        Array(Vec<Object>),
+        // Keys in insertion order.
+        Hash(Vec<(Object, Object)>),
         Module(Module),
         Function(Function),
+        Native(Native),
         Class(Class),
-        Variable(Variable)
+        Variable(Variable),
+        Enum(EnumDecl),
+        EnumValue(EnumValue),
+        Interface(InterfaceDecl),
+        Instance(Instance),
+        Null,
+        // What a Void function call evaluates to.
+        Void
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -257,42 +832,215 @@ pub mod structs {
         pub value: f64
     }
 
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct BigInteger {
+        pub value: BigInt
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Str {
+        pub value: String
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct EnumValue {
+        pub enum_name: String,
+        pub variant: String,
+        pub values: Vec<Object>
+    }
+
     #[derive(Debug, Clone, PartialEq)]
     pub struct Module {
-        path: String,
-        env: Env
+        pub path: String,
+        // Members live in `env.funcs`.
+        pub env: Env
+    }
+
+    pub type NativeFn = Rc<dyn Fn(&[Object]) -> Result<Object, String>>;
+
+    // A function implemented in Rust. The interpreter checks the arguments against `params` before calling it,
+    // an Err becomes a runtime error.
+    #[derive(Clone)]
+    pub struct Native {
+        // Including the module, `math::sqrt`.
+        pub name: String,
+        pub params: Vec<Type>,
+        // Takes any number of values after `params`.
+        pub variadic: bool,
+        pub return_type: Type,
+        pub func: NativeFn,
+        // Natives defined under the same name with other parameters, tried in order when this one does not fit.
+        pub overloads: Vec<Native>
+    }
+
+    impl fmt::Debug for Native {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Native({})", self.name)
+        }
+    }
+
+    impl Native {
+        pub fn signature(&self) -> MethodSignature {
+            MethodSignature{ name: self.name.clone(), params: self.params.clone(), return_type: self.return_type.clone() }
+        }
+
+        // This native followed by its overloads.
+        pub fn candidates(&self) -> Vec<&Native> {
+            std::iter::once(self).chain(&self.overloads).collect()
+        }
+    }
+
+    impl PartialEq for Native {
+        fn eq(&self, other: &Native) -> bool {
+            self.name == other.name
+        }
     }
 
     #[derive(Debug, Clone, PartialEq)]
     pub struct Function {
-        params: Vec<Object>,
-        body: Vec<Object>,
-        env: Env
+        pub decl: FunctionDecl,
+        // Variables a lambda closed over, empty for named functions.
+        pub captured: HashMap<String, Object>
     }
 
     #[derive(Debug, Clone, PartialEq)]
     pub struct Class {
+        pub name: String,
+        pub interfaces: Vec<String>,
+        pub fields: Vec<AST>,
+        // Methods live in `env.funcs`.
         pub env: Env
     }
 
-    impl Add for Object {
-        type Output = Object;
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Instance {
+        pub class: String,
+        // Field names in declaration order, their values live in `env.vars`.
+        pub fields: Vec<String>,
+        pub env: Env
+    }
 
-        fn add(self, other: Self) -> Self::Output {
+    // What `print` shows. Strings are bare at the top level and quoted inside collections.
+    impl fmt::Display for Object {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             match self {
-                Object::Integer(int1) => {
-                    match other {
-                        Object::Integer(int2) => {
-                            Object::Integer(Integer{ value: int1.value + int2.value })
-                        },
-                        _ => {
-                            panic!("");
-                        }
+                Object::Integer(i) => write!(f, "{}", i.value),
+                Object::Decimal(d) => write!(f, "{:?}", d.value),
+                Object::BigInteger(b) => write!(f, "{}", b.value),
+                Object::String(s) => write!(f, "{}", s.value),
+                Object::Boolean(b) => write!(f, "{}", b),
+                Object::Array(items) => {
+                    let items: Vec<String> = items.iter().map(|item| item.nested()).collect();
+                    write!(f, "[{}]", items.join(", "))
+                },
+                Object::Hash(entries) => {
+                    let entries: Vec<String> = entries.iter().map(|(k, v)| format!("{}: {}", k.nested(), v.nested())).collect();
+                    write!(f, "{{{}}}", entries.join(", "))
+                },
+                Object::Module(module) => write!(f, "<module {}>", module.path),
+                Object::Function(func) => write!(f, "<funk {}>", func.decl.name),
+                Object::Native(native) => write!(f, "<funk {}>", native.name),
+                Object::Class(class) => write!(f, "<klass {}>", class.name),
+                Object::Variable(var) => write!(f, "{}", var.value),
+                Object::Enum(decl) => write!(f, "<enum {}>", decl.name),
+                Object::EnumValue(value) if value.values.is_empty() => write!(f, "{}::{}", value.enum_name, value.variant),
+                Object::EnumValue(value) => {
+                    let values: Vec<String> = value.values.iter().map(|v| v.nested()).collect();
+                    write!(f, "{}::{}({})", value.enum_name, value.variant, values.join(", "))
+                },
+                Object::Interface(decl) => write!(f, "<interface {}>", decl.name),
+                Object::Instance(instance) => {
+                    let fields: Vec<String> = instance.fields.iter().map(|name| match instance.env.vars.get(name) {
+                        Some(value) => format!("{}: {}", name, value.nested()),
+                        None => name.clone()
+                    }).collect();
+                    write!(f, "{}({})", instance.class, fields.join(", "))
+                },
+                Object::Null => write!(f, "null"),
+                Object::Void => write!(f, "Void")
+            }
+        }
+    }
+
+    impl Object {
+        // An instance of the builtin Error klass.
+        pub fn error(message: String, trace: String) -> Object {
+            let mut env = Env::new();
+            for (name, value) in [("message", message), ("trace", trace)] {
+                let variable = Variable{ name: name.to_string(), ty: Type::String, value: Box::new(Object::String(Str{ value })) };
+                env.set_var(name.to_string(), Object::Variable(variable));
+            }
+            Object::Instance(Instance{ class: "Error".to_string(), fields: vec!("message".to_string(), "trace".to_string()), env })
+        }
+
+        // How a value shows inside another one.
+        fn nested(&self) -> String {
+            match self {
+                Object::String(s) => format!("{:?}", s.value),
+                Object::Variable(var) => var.value.nested(),
+                other => other.to_string()
+            }
+        }
+
+        // Integer arithmetic is checked, overflowing is an error rather than a silent wrap.
+        pub fn arithmetic(self, op: BinOp, other: Object) -> Result<Object, String> {
+            match (self, other) {
+                (Object::Integer(a), Object::Integer(b)) => {
+                    let result = match op {
+                        BinOp::Add => a.value.checked_add(b.value),
+                        BinOp::Subtract => a.value.checked_sub(b.value),
+                        BinOp::Multiply => a.value.checked_mul(b.value),
+                        BinOp::Divide if b.value == 0 => return Err("Division by zero".to_string()),
+                        BinOp::Divide => a.value.checked_div(b.value),
+                        BinOp::Modulo if b.value == 0 => return Err("Division by zero".to_string()),
+                        BinOp::Modulo => a.value.checked_rem(b.value),
+                        _ => return Err(format!("Unsupported operator [{}]", op))
+                    };
+                    match result {
+                        Some(value) => Ok(Object::Integer(Integer{ value })),
+                        None => Err(format!("Integer overflow in [{} {} {}], use BigInteger or the wrapping_ builtins", a.value, op, b.value))
                     }
                 },
-                _ => {
-                    panic!("");
-                }
+                (Object::Decimal(a), Object::Decimal(b)) => {
+                    let value = match op {
+                        BinOp::Add => a.value + b.value,
+                        BinOp::Subtract => a.value - b.value,
+                        BinOp::Multiply => a.value * b.value,
+                        BinOp::Divide => a.value / b.value,
+                        BinOp::Modulo => a.value % b.value,
+                        _ => return Err(format!("Unsupported operator [{}]", op))
+                    };
+                    Ok(Object::Decimal(Decimal{ value }))
+                },
+                // Mixing an Integer into BigInteger arithmetic widens it.
+                (a @ Object::BigInteger(_), b) | (a, b @ Object::BigInteger(_)) => {
+                    let (a, b) = match (a.to_big(), b.to_big()) {
+                        (Some(a), Some(b)) => (a, b),
+                        _ => return Err(format!("Cannot apply [{}] to [{}] and [{}]", op, a, b))
+                    };
+                    let value = match op {
+                        BinOp::Add => a + b,
+                        BinOp::Subtract => a - b,
+                        BinOp::Multiply => a * b,
+                        BinOp::Divide if b.is_zero() => return Err("Division by zero".to_string()),
+                        BinOp::Divide => a / b,
+                        BinOp::Modulo => match a.div_rem(&b) {
+                            Some((_, remainder)) => remainder,
+                            None => return Err("Division by zero".to_string())
+                        },
+                        _ => return Err(format!("Unsupported operator [{}]", op))
+                    };
+                    Ok(Object::BigInteger(BigInteger{ value }))
+                },
+                (a, b) => Err(format!("Cannot apply [{}] to [{}] and [{}]", op, a, b))
+            }
+        }
+
+        pub fn to_big(&self) -> Option<BigInt> {
+            match self {
+                Object::Integer(int) => Some(BigInt::from_i64(int.value)),
+                Object::BigInteger(big) => Some(big.value.clone()),
+                _ => None
             }
         }
     }
@@ -301,12 +1049,14 @@ pub mod structs {
     pub struct Env {
         pub vars: HashMap<String, Object>,
         pub funcs: HashMap<String, Object>,
-        pub mods: HashMap<String, Object>
+        pub mods: HashMap<String, Object>,
+        pub types: HashMap<String, Object>
     }
 
+    #[allow(dead_code)]
     impl Env {
         pub fn new() -> Env {
-            Env{ vars: HashMap::new(), funcs: HashMap::new(), mods: HashMap::new() }
+            Env{ vars: HashMap::new(), funcs: HashMap::new(), mods: HashMap::new(), types: HashMap::new() }
         }
 
         pub fn get_var(&mut self, name: String) -> Option<&mut Object> {
@@ -328,5 +1078,33 @@ pub mod structs {
         pub fn remove_mod(&mut self, name: String) -> Option<Object> {
             self.mods.remove(&name)
         }
+
+        // `name`, or `module::name` when a module is given.
+        pub fn get_native(&self, module: Option<&str>, name: &str) -> Option<&Native> {
+            let funcs = match module {
+                None => &self.funcs,
+                Some(module) => match self.mods.get(module) {
+                    Some(Object::Module(module)) => &module.env.funcs,
+                    _ => return None
+                }
+            };
+            match funcs.get(name) {
+                Some(Object::Native(native)) => Some(native),
+                _ => None
+            }
+        }
+        pub fn get_func(&mut self, name: String) -> Option<&mut Object> {
+            self.funcs.get_mut(&name)
+        }
+        pub fn set_func(&mut self, name: String, val: Object) -> Option<Object> {
+            self.funcs.insert(name, val)
+        }
+
+        pub fn get_type(&mut self, name: String) -> Option<&mut Object> {
+            self.types.get_mut(&name)
+        }
+        pub fn set_type(&mut self, name: String, val: Object) -> Option<Object> {
+            self.types.insert(name, val)
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,53 @@
+// Runs every tests/scripts/*.fk and compares what it prints with the .out file next to it.
+// Leading comments set up the run:
+//   // flags: --no-io      flags for the interpreter
+//   // args: a b          arguments for the script
+//   // exit: 3            the expected exit code, 0 otherwise
+// A .in file is fed to standard input and FUNK_SCRIPT_TEST is set to `set`. Scripts run in a scratch directory, so relative paths
+// they write to stay out of the tree.
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+fn directive<'a>(code: &'a str, key: &str) -> Vec<&'a str> {
+  code.lines().take_while(|line| line.starts_with("//"))
+    .filter_map(|line| line.strip_prefix("// ").and_then(|line| line.strip_prefix(key)).and_then(|line| line.strip_prefix(":")))
+    .flat_map(|values| values.split_whitespace()).collect()
+}
+
+#[test]
+fn scripts_print_what_they_should() {
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("scripts");
+  let mut scripts: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path())
+    .filter(|path| path.extension().is_some_and(|ext| ext == "fk")).collect();
+  scripts.sort();
+  assert!(! scripts.is_empty());
+
+  for script in scripts {
+    let code = fs::read_to_string(&script).unwrap();
+    let exit: i32 = directive(&code, "exit").first().map_or(0, |code| code.parse().unwrap());
+    let expected = fs::read_to_string(script.with_extension("out")).unwrap();
+    let input = fs::read_to_string(script.with_extension("in")).unwrap_or_default();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_funk-lang"))
+      .args(directive(&code, "flags")).arg(&script).args(directive(&code, "args"))
+      .current_dir(env!("CARGO_TARGET_TMPDIR")).env("FUNK_SCRIPT_TEST", "set")
+      .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(exit), "{} exited with:\n{}", script.display(), String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected, "{}", script.display());
+  }
+}
+
+#[test]
+fn command_line_mistakes_print_the_usage() {
+  for args in [vec!("missing_script.fk"), vec!("--no-io"), vec!()] {
+    let output = Command::new(env!("CARGO_BIN_EXE_funk-lang")).args(&args).current_dir(env!("CARGO_TARGET_TMPDIR")).output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{:?}: {}", args, stderr);
+    assert!(stderr.starts_with("error: ") && stderr.contains("\nusage: funk-lang "), "{:?}: {}", args, stderr);
+  }
+}